@@ -0,0 +1,118 @@
+// Static disassembly of the PRG ROM visible at 0x8000 - 0xFFFF at power on
+// The code is found by following the execution from the NMI, reset and IRQ
+// vectors, through branches, JMP and JSR; the bytes that are never reached
+// are printed as data
+// cargo run --example disasm -- <rom_file>
+
+use std::{collections::BTreeSet, env, process};
+
+use nes_emulator::{
+    cpu::{opcode_info, AddressingMode, OpcodeInfo},
+    disasm::disassemble_with_labels,
+    mem::{IRQ_VECTOR, NMI_VECTOR, PRG_ROM_START, RESET_VECTOR},
+    ram::RAM,
+};
+
+const PRG_ROM_SIZE: usize = 0x8000;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 2 {
+        eprintln!("Usage: cargo run --example disasm -- <rom_file>");
+        process::exit(1);
+    }
+    let ram: RAM = RAM::from_file(&args[1]).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let vectors: [(&str, u16); 3] = [
+        ("NMI", NMI_VECTOR),
+        ("RESET", RESET_VECTOR),
+        ("IRQ", IRQ_VECTOR),
+    ];
+    let mut labels: BTreeSet<u16> = BTreeSet::new();
+    let mut to_visit: Vec<u16> = Vec::new();
+    for (name, vector) in vectors {
+        let addr: u16 = read_word(&ram, vector);
+        println!("; {} vector: ${:04X}", name, addr);
+        labels.insert(addr);
+        to_visit.push(addr);
+    }
+    println!();
+
+    // start address of each decoded instruction
+    let mut code: BTreeSet<u16> = BTreeSet::new();
+    while let Some(mut addr) = to_visit.pop() {
+        while addr >= PRG_ROM_START && !code.contains(&addr) {
+            let Some(info) = opcode_info(ram.peek(addr)) else {
+                break;
+            };
+            code.insert(addr);
+            let operand: u16 = operand(&ram, addr, &info);
+            match (info.mnemonic, info.mode) {
+                ("JMP", AddressingMode::Absolute) | ("JSR", _) => {
+                    labels.insert(operand);
+                    to_visit.push(operand);
+                }
+                (_, AddressingMode::Relative) => {
+                    let target: u16 = branch_target(addr, operand);
+                    labels.insert(target);
+                    to_visit.push(target);
+                }
+                _ => {}
+            }
+            // the execution does not go on after these
+            if matches!(info.mnemonic, "JMP" | "RTS" | "RTI" | "BRK" | "JAM") {
+                break;
+            }
+            addr = match addr.checked_add(info.length as u16) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+    }
+
+    let mut addr: usize = PRG_ROM_START as usize;
+    while addr < PRG_ROM_START as usize + PRG_ROM_SIZE {
+        let pc: u16 = addr as u16;
+        if labels.contains(&pc) {
+            println!("L{:04X}:", pc);
+        }
+        match opcode_info(ram.peek(pc)).filter(|_| code.contains(&pc)) {
+            Some(info) => {
+                let bytes: Vec<String> = (0..info.length as u16)
+                    .map(|i| format!("{:02X}", ram.peek(pc.wrapping_add(i))))
+                    .collect();
+                // absolute addresses that are labels are printed as such
+                let instruction: String = disassemble_with_labels(&ram, pc, |target| {
+                    labels.contains(&target).then(|| format!("L{:04X}", target))
+                });
+                println!("  {:04X}  {:<8}  {}", pc, bytes.join(" "), instruction);
+                addr += info.length as usize;
+            }
+            None => {
+                let byte: u8 = ram.peek(pc);
+                println!("  {:04X}  {:02X}        .byte ${:02X}", pc, byte, byte);
+                addr += 1;
+            }
+        }
+    }
+}
+
+fn read_word(ram: &RAM, addr: u16) -> u16 {
+    u16::from_le_bytes([ram.peek(addr), ram.peek(addr.wrapping_add(1))])
+}
+
+// The operand bytes of the instruction at addr, 0 if it has none
+fn operand(ram: &RAM, addr: u16, info: &OpcodeInfo) -> u16 {
+    match info.length {
+        1 => 0,
+        2 => ram.peek(addr.wrapping_add(1)) as u16,
+        _ => read_word(ram, addr.wrapping_add(1)),
+    }
+}
+
+fn branch_target(addr: u16, offset: u16) -> u16 {
+    addr.wrapping_add(2).wrapping_add(offset as u8 as i8 as u16)
+}
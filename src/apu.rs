@@ -0,0 +1,266 @@
+// APU registers, as seen by the CPU:
+// 0x4000 - 0x4003: Pulse 1
+// 0x4004 - 0x4007: Pulse 2
+// 0x4008 - 0x400B: Triangle
+// 0x4015: Channel enable (write), length counter and IRQ status (read)
+// 0x4017: Frame counter mode and IRQ inhibit
+
+use std::{
+    cell::Cell,
+    collections::{vec_deque::Drain, VecDeque},
+};
+
+use serde::{Deserialize, Serialize};
+
+use self::{pulse::Pulse, triangle::Triangle};
+use crate::{
+    cartridge::Region,
+    mem::{APU_FRAME_COUNTER, APU_STATUS},
+};
+
+mod envelope;
+mod pulse;
+mod triangle;
+
+pub const DEFAULT_SAMPLE_RATE: f64 = 44100.0;
+// About a tenth of a second of audio at the default sample rate, the oldest
+// samples are dropped when the frontend does not keep up
+const SAMPLE_BUFFER_SIZE: usize = 4096;
+
+// The frame counter clocks the envelopes and the triangle linear counter on
+// quarter frames, and the length counters and sweeps on half frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum FrameCounterMode {
+    // raises an IRQ at the end of each sequence unless inhibited
+    FourStep,
+    FiveStep,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct APU {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    // CPU cycles since the power on
+    cycles: u64,
+    frame_counter_mode: FrameCounterMode,
+    frame_counter_cycle: u64,
+    irq_inhibit: bool,
+    // a Cell since reading 0x4015 acknowledges the IRQ
+    frame_irq: Cell<bool>,
+    // CPU cycles between two output samples, and the progress towards the next one
+    cycles_per_sample: f64,
+    sample_timer: f64,
+    // mixed output, drained by the frontend
+    #[serde(skip)]
+    samples: VecDeque<f32>,
+}
+
+impl APU {
+    pub fn new() -> APU {
+        APU {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::default(),
+            cycles: 0,
+            frame_counter_mode: FrameCounterMode::FourStep,
+            frame_counter_cycle: 0,
+            irq_inhibit: false,
+            frame_irq: Cell::new(false),
+            cycles_per_sample: Region::Ntsc.cpu_clock_rate() / DEFAULT_SAMPLE_RATE,
+            sample_timer: 0.0,
+            samples: VecDeque::with_capacity(SAMPLE_BUFFER_SIZE),
+        }
+    }
+
+    // Number of samples per second pushed in the buffer
+    pub fn set_sample_rate(&mut self, sample_rate: f64, region: Region) {
+        self.cycles_per_sample = region.cpu_clock_rate() / sample_rate;
+    }
+
+    // Silences all the channels, as the reset button does
+    pub fn reset(&mut self) {
+        self.write_register(APU_STATUS, 0);
+        self.frame_counter_cycle = 0;
+    }
+
+    pub fn read_register(&self, addr: u16) -> u8 {
+        match addr {
+            APU_STATUS => {
+                let status: u8 = self.pulse1.is_active() as u8
+                    | (self.pulse2.is_active() as u8) << 1
+                    | (self.triangle.is_active() as u8) << 2
+                    | (self.frame_irq.get() as u8) << 6;
+                self.frame_irq.set(false);
+                status
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write_register(addr - 0x4000, data),
+            0x4004..=0x4007 => self.pulse2.write_register(addr - 0x4004, data),
+            0x4008..=0x400B => self.triangle.write_register(addr - 0x4008, data),
+            APU_STATUS => {
+                self.pulse1.set_enabled(data & 0b001 != 0);
+                self.pulse2.set_enabled(data & 0b010 != 0);
+                self.triangle.set_enabled(data & 0b100 != 0);
+            }
+            APU_FRAME_COUNTER => {
+                self.frame_counter_mode = if data & 0b10000000 != 0 {
+                    FrameCounterMode::FiveStep
+                } else {
+                    FrameCounterMode::FourStep
+                };
+                self.irq_inhibit = data & 0b01000000 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq.set(false);
+                }
+                self.frame_counter_cycle = 0;
+                // the 5-step mode clocks all the units right away
+                if self.frame_counter_mode == FrameCounterMode::FiveStep {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Advances the APU by one CPU cycle
+    pub fn tick(&mut self) {
+        // the pulse timers run at half the CPU clock
+        if self.cycles % 2 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.triangle.clock_timer();
+        self.clock_frame_counter();
+        self.cycles += 1;
+
+        self.sample_timer += 1.0;
+        if self.sample_timer >= self.cycles_per_sample {
+            self.sample_timer -= self.cycles_per_sample;
+            if self.samples.len() == SAMPLE_BUFFER_SIZE {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(self.sample());
+        }
+    }
+
+    // Steps happen at the same CPU cycles in both modes, the 5-step mode
+    // only has a longer last step
+    fn clock_frame_counter(&mut self) {
+        self.frame_counter_cycle += 1;
+        match (self.frame_counter_mode, self.frame_counter_cycle) {
+            (_, 7457) | (_, 22371) => self.clock_quarter_frame(),
+            (_, 14913) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            (FrameCounterMode::FourStep, 29828) => self.raise_frame_irq(),
+            (FrameCounterMode::FourStep, 29829) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                self.raise_frame_irq();
+            }
+            (FrameCounterMode::FourStep, 29830) => {
+                self.raise_frame_irq();
+                self.frame_counter_cycle = 0;
+            }
+            (FrameCounterMode::FiveStep, 37281) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            (FrameCounterMode::FiveStep, 37282) => self.frame_counter_cycle = 0,
+            _ => {}
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.clock_quarter_frame();
+        self.pulse2.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.clock_half_frame();
+        self.pulse2.clock_half_frame();
+        self.triangle.clock_half_frame();
+    }
+
+    fn raise_frame_irq(&mut self) {
+        if !self.irq_inhibit {
+            self.frame_irq.set(true);
+        }
+    }
+
+    // The IRQ line stays asserted until acknowledged through 0x4015 or 0x4017
+    pub fn irq(&self) -> bool {
+        self.frame_irq.get()
+    }
+
+    // Current output of the channels mixed together, between 0 and 1
+    // The pulses and the triangle go through separate non-linear mixers
+    pub fn sample(&self) -> f32 {
+        let pulses: f32 = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_out: f32 = if pulses == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulses + 100.0)
+        };
+        let triangle: f32 = self.triangle.output() as f32;
+        let tnd_out: f32 = if triangle == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / (triangle / 8227.0) + 100.0)
+        };
+        pulse_out + tnd_out
+    }
+
+    // Takes the samples produced since the last call
+    pub fn drain_samples(&mut self) -> Drain<'_, f32> {
+        self.samples.drain(..)
+    }
+}
+
+impl Default for APU {
+    fn default() -> APU {
+        APU::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(apu: &mut APU, cycles: u64) {
+        for _ in 0..cycles {
+            apu.tick();
+        }
+    }
+
+    #[test]
+    fn four_step_mode_raises_the_frame_irq_at_cycle_29828() {
+        let mut apu: APU = APU::new();
+        tick(&mut apu, 29827);
+        assert!(!apu.irq());
+        tick(&mut apu, 1);
+        assert!(apu.irq());
+        // reading the status acknowledges the IRQ
+        assert_eq!(apu.read_register(APU_STATUS) & 0b01000000, 0b01000000);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn frame_irq_is_not_raised_when_inhibited_or_in_five_step_mode() {
+        for data in [0b01000000, 0b10000000] {
+            let mut apu: APU = APU::new();
+            apu.write_register(APU_FRAME_COUNTER, data);
+            tick(&mut apu, 2 * 37282);
+            assert!(!apu.irq());
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+
+// Durations loaded in the length counter, indexed by the top 5 bits of the last channel register
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+// Volume of the pulse and noise channels, either constant or decaying from 15
+// every time the divider reaches 0
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Envelope {
+    start: bool,
+    looping: bool, // shares its bit with the length counter halt flag
+    constant_volume: bool,
+    period: u8, // also the constant volume
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    // --LC VVVV
+    pub fn write_control(&mut self, data: u8) {
+        self.looping = data & 0b00100000 != 0;
+        self.constant_volume = data & 0b00010000 != 0;
+        self.period = data & 0b1111;
+    }
+
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    // Clocked by the frame counter on every quarter frame
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.period;
+        } else if self.divider == 0 {
+            self.divider = self.period;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn volume(&self) -> u8 {
+        if self.constant_volume {
+            self.period
+        } else {
+            self.decay
+        }
+    }
+}
+
+// Silences its channel once it reaches 0, unless halted
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LengthCounter {
+    enabled: bool,
+    halted: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    // Disabling the channel through 0x4015 clears the counter right away
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub fn set_halted(&mut self, halted: bool) {
+        self.halted = halted;
+    }
+
+    // Loads the counter from the top 5 bits of the last channel register
+    pub fn load(&mut self, data: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(data >> 3) as usize];
+        }
+    }
+
+    // Clocked by the frame counter on every half frame
+    pub fn clock(&mut self) {
+        if !self.halted && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+}
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+
+use super::envelope::{Envelope, LengthCounter};
+
+// Registers, 0x4000 - 0x4003 for pulse 1 and 0x4004 - 0x4007 for pulse 2:
+// 0: DDLC VVVV  duty, length counter halt / envelope loop, constant volume, volume / envelope period
+// 1: EPPP NSSS  sweep enabled, period, negate, shift
+// 2: TTTT TTTT  timer low
+// 3: LLLL LTTT  length counter load, timer high
+
+const DUTY_SEQUENCES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+// Periods above this value mute the channel
+const MAX_TIMER_PERIOD: u16 = 0x7FF;
+
+// Bends the timer period up or down every half frame
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    divider: u8,
+    reload: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Pulse {
+    // pulse 1 negates the sweep change with one's complement, pulse 2 with two's complement
+    ones_complement: bool,
+    duty: u8,
+    duty_step: u8,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    length_counter: LengthCounter,
+    sweep: Sweep,
+}
+
+impl Pulse {
+    pub fn new(ones_complement: bool) -> Pulse {
+        Pulse {
+            ones_complement,
+            duty: 0,
+            duty_step: 0,
+            timer_period: 0,
+            timer: 0,
+            envelope: Envelope::default(),
+            length_counter: LengthCounter::default(),
+            sweep: Sweep::default(),
+        }
+    }
+
+    // addr is the register index, from 0 to 3
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0 => {
+                self.duty = data >> 6;
+                self.length_counter.set_halted(data & 0b00100000 != 0);
+                self.envelope.write_control(data);
+            }
+            1 => {
+                self.sweep.enabled = data & 0b10000000 != 0;
+                self.sweep.period = (data >> 4) & 0b111;
+                self.sweep.negate = data & 0b00001000 != 0;
+                self.sweep.shift = data & 0b111;
+                self.sweep.reload = true;
+            }
+            2 => self.timer_period = (self.timer_period & 0xFF00) | data as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data & 0b111) as u16) << 8;
+                self.length_counter.load(data);
+                self.envelope.restart();
+                self.duty_step = 0;
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    // Clocked every APU cycle, i.e. every other CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.duty_step = (self.duty_step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+        if self.sweep.divider == 0
+            && self.sweep.enabled
+            && self.sweep.shift > 0
+            && !self.is_sweep_muting()
+        {
+            self.timer_period = self.sweep_target();
+        }
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change: u16 = self.timer_period >> self.sweep.shift;
+        if !self.sweep.negate {
+            self.timer_period + change
+        } else if self.ones_complement {
+            self.timer_period.saturating_sub(change + 1)
+        } else {
+            self.timer_period.saturating_sub(change)
+        }
+    }
+
+    // The sweep unit mutes the channel even when it is disabled
+    fn is_sweep_muting(&self) -> bool {
+        self.timer_period < 8 || self.sweep_target() > MAX_TIMER_PERIOD
+    }
+
+    // Between 0 and 15
+    pub fn output(&self) -> u8 {
+        if !self.length_counter.is_active()
+            || self.is_sweep_muting()
+            || DUTY_SEQUENCES[self.duty as usize][self.duty_step as usize] == 0
+        {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
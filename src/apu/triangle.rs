@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+use super::envelope::LengthCounter;
+
+// Registers, 0x4008 - 0x400B:
+// 0x4008: CRRR RRRR  length counter halt / linear counter control, linear counter reload value
+// 0x4009: unused
+// 0x400A: TTTT TTTT  timer low
+// 0x400B: LLLL LTTT  length counter load, timer high
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Triangle {
+    control: bool,
+    linear_counter_period: u8,
+    linear_counter: u8,
+    linear_counter_reload: bool,
+    timer_period: u16,
+    timer: u16,
+    step: u8,
+    length_counter: LengthCounter,
+}
+
+impl Triangle {
+    // addr is the register index, from 0 to 3
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0 => {
+                self.control = data & 0b10000000 != 0;
+                self.length_counter.set_halted(self.control);
+                self.linear_counter_period = data & 0b01111111;
+            }
+            1 => {}
+            2 => self.timer_period = (self.timer_period & 0xFF00) | data as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data & 0b111) as u16) << 8;
+                self.length_counter.load(data);
+                self.linear_counter_reload = true;
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    // Unlike the pulses, clocked every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            // the waveform freezes rather than going silent when a counter runs out
+            if self.linear_counter > 0 && self.length_counter.is_active() {
+                self.step = (self.step + 1) % SEQUENCE.len() as u8;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        if self.linear_counter_reload {
+            self.linear_counter = self.linear_counter_period;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_counter_reload = false;
+        }
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    // Between 0 and 15
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.step as usize]
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    BuildStreamError, DefaultStreamConfigError, FromSample, PlayStreamError, SampleFormat,
+    SizedSample, Stream, StreamConfig,
+};
+
+// Samples queued ahead of the output stream; past this, the oldest ones are
+// dropped so that the latency does not grow when the emulation runs too fast
+const MAX_QUEUED_SAMPLES: usize = 8192;
+
+#[derive(Debug)]
+pub enum AudioError {
+    NoOutputDevice,
+    Config(DefaultStreamConfigError),
+    BuildStream(BuildStreamError),
+    PlayStream(PlayStreamError),
+    UnsupportedSampleFormat(SampleFormat),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AudioError::NoOutputDevice => write!(f, "No audio output device"),
+            AudioError::Config(err) => write!(f, "Could not get the audio output config: {}", err),
+            AudioError::BuildStream(err) => write!(f, "Could not open the audio stream: {}", err),
+            AudioError::PlayStream(err) => write!(f, "Could not start the audio stream: {}", err),
+            AudioError::UnsupportedSampleFormat(format) => {
+                write!(f, "Unsupported audio sample format {}", format)
+            }
+        }
+    }
+}
+
+impl Error for AudioError {}
+
+// Samples waiting to be played, and the last one played to fill in underruns
+#[derive(Debug, Default)]
+struct SampleQueue {
+    samples: VecDeque<f32>,
+    last: f32,
+}
+
+// Plays the APU samples on the default output device
+pub struct Audio {
+    // the sound stops when the stream is dropped
+    _stream: Stream,
+    queue: Arc<Mutex<SampleQueue>>,
+    sample_rate: u32,
+}
+
+impl Audio {
+    pub fn new() -> Result<Audio, AudioError> {
+        let device: cpal::Device = cpal::default_host()
+            .default_output_device()
+            .ok_or(AudioError::NoOutputDevice)?;
+        let supported_config: cpal::SupportedStreamConfig =
+            device.default_output_config().map_err(AudioError::Config)?;
+        let sample_format: SampleFormat = supported_config.sample_format();
+        let config: StreamConfig = supported_config.config();
+        let queue: Arc<Mutex<SampleQueue>> = Arc::new(Mutex::new(SampleQueue::default()));
+        let stream: Stream = match sample_format {
+            SampleFormat::F32 => Audio::build_stream::<f32>(&device, &config, queue.clone()),
+            SampleFormat::I16 => Audio::build_stream::<i16>(&device, &config, queue.clone()),
+            SampleFormat::U16 => Audio::build_stream::<u16>(&device, &config, queue.clone()),
+            format => return Err(AudioError::UnsupportedSampleFormat(format)),
+        }?;
+        stream.play().map_err(AudioError::PlayStream)?;
+        Ok(Audio {
+            _stream: stream,
+            queue,
+            sample_rate: config.sample_rate.0,
+        })
+    }
+
+    fn build_stream<T: SizedSample + FromSample<f32>>(
+        device: &cpal::Device,
+        config: &StreamConfig,
+        queue: Arc<Mutex<SampleQueue>>,
+    ) -> Result<Stream, AudioError> {
+        let channels: usize = config.channels as usize;
+        device
+            .build_output_stream(
+                config,
+                move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                    let mut queue = queue.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        // on underrun, hold the last sample rather than dropping to 0 with a click
+                        let sample: f32 = queue.samples.pop_front().unwrap_or(queue.last);
+                        queue.last = sample;
+                        frame.fill(T::from_sample(sample));
+                    }
+                },
+                |err| eprintln!("Audio stream error: {}", err),
+                None,
+            )
+            .map_err(AudioError::BuildStream)
+    }
+
+    // The rate the APU should produce samples at, see NES::set_sample_rate
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn queue_samples(&self, samples: impl Iterator<Item = f32>) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.samples.extend(samples);
+        let excess: usize = queue.samples.len().saturating_sub(MAX_QUEUED_SAMPLES);
+        queue.samples.drain(..excess);
+    }
+}
+
+impl fmt::Debug for Audio {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Audio")
+            .field("sample_rate", &self.sample_rate)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,407 @@
+// egui debugger: the frame buffer, the CPU registers, a disassembly around
+// the PC, a memory viewer and the breakpoints of a NES that runs or steps on
+// demand
+// Controller 1: arrows, X (A), Z (B), Tab (Select), Enter (Start), while no
+// text field has the focus
+
+use std::{collections::HashMap, env};
+
+use eframe::egui::{
+    self, Color32, ColorImage, Key, RichText, ScrollArea, TextStyle, TextureHandle, TextureOptions,
+};
+use nes_emulator::{
+    controller::{Button, KeyMap},
+    cpu::{opcode_info, StatusFlag, CPU},
+    debugger::{hex_dump, Debugger, StopReason},
+    disasm::{instruction_length, InstructionIter},
+    nes::{NesBuilder, NES},
+    ppu::{PPU, SCREEN_HEIGHT, SCREEN_WIDTH},
+    ram::RAM,
+};
+
+const SCALE: f32 = 2.0;
+// instructions shown in the disassembly, starting a few before the PC
+const DISASSEMBLY_LINES: usize = 32;
+const DISASSEMBLY_CONTEXT: usize = 4;
+const MEMORY_LINE_SIZE: usize = 16;
+// instructions run between two checks of the frame count while running
+const RUN_STEPS: u64 = 64;
+
+const DEFAULT_KEYS: [(Key, u8, Button); 8] = [
+    (Key::X, 0, Button::A),
+    (Key::Z, 0, Button::B),
+    (Key::Tab, 0, Button::Select),
+    (Key::Enter, 0, Button::Start),
+    (Key::ArrowUp, 0, Button::Up),
+    (Key::ArrowDown, 0, Button::Down),
+    (Key::ArrowLeft, 0, Button::Left),
+    (Key::ArrowRight, 0, Button::Right),
+];
+
+const FLAGS: [(char, StatusFlag); 6] = [
+    ('N', StatusFlag::Negative),
+    ('V', StatusFlag::Overflow),
+    ('D', StatusFlag::DecimalMode),
+    ('I', StatusFlag::InterruptDisable),
+    ('Z', StatusFlag::Zero),
+    ('C', StatusFlag::Carry),
+];
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 2 {
+        eprintln!("Usage: cargo run --features egui --bin nes_egui -- <rom_file>");
+        std::process::exit(1);
+    }
+    let nes: NES = NesBuilder::from_file(&args[1])
+        .trace(false)
+        .build()
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        });
+    let result: eframe::Result = eframe::run_native(
+        "NES debugger",
+        eframe::NativeOptions::default(),
+        Box::new(|_| Ok(Box::new(DebuggerApp::new(nes)))),
+    );
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+struct DebuggerApp {
+    nes: NES,
+    debugger: Debugger,
+    key_map: KeyMap<Key>,
+    running: bool,
+    // where the execution stops besides the breakpoints, for step over and
+    // run to cursor
+    run_to: Option<u16>,
+    // first address of the disassembly, kept while the PC stays in view
+    disassembly_start: u16,
+    breakpoint_input: String,
+    memory_input: String,
+    // line of the memory viewer to scroll to
+    memory_scroll: Option<usize>,
+    // why the execution last stopped
+    status: String,
+    screen: Option<TextureHandle>,
+}
+
+impl DebuggerApp {
+    fn new(nes: NES) -> DebuggerApp {
+        let mut key_map: KeyMap<Key> = KeyMap::new();
+        for (key, port, button) in DEFAULT_KEYS {
+            key_map.bind(key, port, button);
+        }
+        let pc: u16 = nes.cpu().pc();
+        DebuggerApp {
+            nes,
+            debugger: Debugger::new(),
+            key_map,
+            running: false,
+            run_to: None,
+            disassembly_start: pc,
+            breakpoint_input: String::new(),
+            memory_input: String::new(),
+            memory_scroll: None,
+            status: String::from("Paused"),
+            screen: None,
+        }
+    }
+
+    fn step(&mut self) {
+        self.running = false;
+        self.run_to = None;
+        match self.debugger.run_until_break(&mut self.nes, 1) {
+            Ok(_) => self.status = String::from("Paused"),
+            Err(err) => self.status = err.to_string(),
+        }
+    }
+
+    // Steps over JSR by running until the instruction after it
+    fn step_over(&mut self) {
+        let pc: u16 = self.nes.cpu().pc();
+        match opcode_info(self.nes.ram().peek(pc)) {
+            Some(info) if info.mnemonic == "JSR" => {
+                self.run_to = Some(pc.wrapping_add(info.length as u16));
+                self.running = true;
+            }
+            _ => self.step(),
+        }
+    }
+
+    fn run_to_cursor(&mut self, addr: u16) {
+        self.run_to = Some(addr);
+        self.running = true;
+    }
+
+    fn pause(&mut self, status: String) {
+        self.running = false;
+        self.run_to = None;
+        self.status = status;
+    }
+
+    // Runs until the end of the frame, a breakpoint or the run to address
+    fn run_frame(&mut self) {
+        let frame_count: u64 = self.nes.ppu().frame_count();
+        while self.nes.ppu().frame_count() == frame_count {
+            let budget: u64 = if self.run_to.is_some() { 1 } else { RUN_STEPS };
+            match self.debugger.run_until_break(&mut self.nes, budget) {
+                Ok(stop) => match stop.reason {
+                    StopReason::Breakpoint(addr) => {
+                        return self.pause(format!("Breakpoint at ${:04X}", addr));
+                    }
+                    StopReason::Watchpoint(hit) => {
+                        return self.pause(format!(
+                            "Watchpoint: {:?} of ${:02X} at ${:04X}",
+                            hit.access, hit.value, hit.addr
+                        ));
+                    }
+                    StopReason::StepBudgetExhausted => {}
+                },
+                Err(err) => return self.pause(err.to_string()),
+            }
+            if self.run_to == Some(self.nes.cpu().pc()) {
+                return self.pause(String::from("Paused"));
+            }
+        }
+        // the APU keeps producing samples, nothing plays them here
+        self.nes.apu_mut().drain_samples();
+    }
+
+    fn update_controllers(&mut self, ctx: &egui::Context) {
+        let typing: bool = ctx.memory(|memory| memory.focused().is_some());
+        let buttons: u8 = ctx.input(|input| {
+            self.key_map
+                .controller_state(0, |&key| !typing && input.key_down(key))
+                .bits()
+        });
+        self.nes.controller_mut(0).set_buttons(buttons);
+    }
+
+    fn screen_panel(&mut self, ui: &mut egui::Ui) {
+        let image: ColorImage = ColorImage::from_rgba_unmultiplied(
+            [SCREEN_WIDTH, SCREEN_HEIGHT],
+            self.nes.ppu().frame_buffer(),
+        );
+        let screen: &mut TextureHandle = self.screen.get_or_insert_with(|| {
+            ui.ctx()
+                .load_texture("screen", image.clone(), TextureOptions::NEAREST)
+        });
+        screen.set(image, TextureOptions::NEAREST);
+        let size: egui::Vec2 =
+            egui::vec2(SCREEN_WIDTH as f32 * SCALE, SCREEN_HEIGHT as f32 * SCALE);
+        ui.image((screen.id(), size));
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if self.running {
+                if ui.button("Pause").clicked() {
+                    self.pause(String::from("Paused"));
+                }
+            } else if ui.button("Run").clicked() {
+                self.running = true;
+                self.status = String::from("Running");
+            }
+            if ui.button("Step").clicked() {
+                self.step();
+            }
+            if ui.button("Step over").clicked() {
+                self.step_over();
+            }
+            if ui.button("Reset").clicked() {
+                self.nes.reset();
+            }
+        });
+        // hide a layer of the picture, the game still runs with it
+        ui.horizontal(|ui| {
+            let ppu: &mut PPU = self.nes.ppu_mut();
+            let mut show_background: bool = ppu.show_background();
+            if ui.checkbox(&mut show_background, "Background").changed() {
+                ppu.set_show_background(show_background);
+            }
+            let mut show_sprites: bool = ppu.show_sprites();
+            if ui.checkbox(&mut show_sprites, "Sprites").changed() {
+                ppu.set_show_sprites(show_sprites);
+            }
+        });
+        ui.label(&self.status);
+    }
+
+    fn registers(&self, ui: &mut egui::Ui) {
+        let nes: &NES = &self.nes;
+        let cpu: &CPU = nes.cpu();
+        let flags: String = FLAGS
+            .iter()
+            .map(|&(name, flag)| if cpu.flag(flag) { name } else { '-' })
+            .collect();
+        let lines: [String; 4] = [
+            format!("PC ${:04X}  SP ${:02X}", cpu.pc(), cpu.sp()),
+            format!("A ${:02X}  X ${:02X}  Y ${:02X}", cpu.a(), cpu.x(), cpu.y()),
+            format!("P {}", flags),
+            format!("Cycles {}", cpu.cycles()),
+        ];
+        for line in lines {
+            ui.monospace(line);
+        }
+        ui.monospace(format!(
+            "Scanline {}  frame {}",
+            nes.ppu().scanline(),
+            nes.ppu().frame_count()
+        ));
+    }
+
+    // Click on an instruction to toggle its breakpoint, right click to run to it
+    fn disassembly(&mut self, ui: &mut egui::Ui) {
+        let pc: u16 = self.nes.cpu().pc();
+        let mut lines: Vec<(u16, String)> =
+            InstructionIter::new(self.nes.ram(), self.disassembly_start)
+                .take(DISASSEMBLY_LINES)
+                .collect();
+        let pc_index: Option<usize> = lines.iter().position(|&(addr, _)| addr == pc);
+        if pc_index.is_none_or(|index| index + DISASSEMBLY_CONTEXT >= DISASSEMBLY_LINES) {
+            // the instructions before the PC cannot be decoded backwards reliably,
+            // the view starts at the PC instead
+            self.disassembly_start = pc;
+            lines = InstructionIter::new(self.nes.ram(), pc)
+                .take(DISASSEMBLY_LINES)
+                .collect();
+        }
+        let breakpoints: HashMap<u16, bool> = self.debugger.breakpoints().collect();
+        let mut toggle: Option<u16> = None;
+        let mut run_to: Option<u16> = None;
+        for (addr, instruction) in lines {
+            let marker: &str = match breakpoints.get(&addr) {
+                Some(true) => "●",
+                Some(false) => "○",
+                None => " ",
+            };
+            let bytes: Vec<String> = (0..instruction_length(self.nes.ram(), addr) as u16)
+                .map(|i| format!("{:02X}", self.nes.ram().peek(addr.wrapping_add(i))))
+                .collect();
+            let text: String = format!(
+                "{} {} {:04X}  {:<8}  {}",
+                marker,
+                if addr == pc { ">" } else { " " },
+                addr,
+                bytes.join(" "),
+                instruction
+            );
+            let mut text: RichText = RichText::new(text).monospace();
+            if addr == pc {
+                text = text.color(Color32::YELLOW);
+            }
+            let response: egui::Response =
+                ui.add(egui::Label::new(text).sense(egui::Sense::click()));
+            if response.clicked() {
+                toggle = Some(addr);
+            }
+            if response.secondary_clicked() {
+                run_to = Some(addr);
+            }
+        }
+        if let Some(addr) = toggle {
+            match breakpoints.get(&addr) {
+                Some(_) => self.debugger.remove_breakpoint(addr),
+                None => self.debugger.add_breakpoint(addr),
+            }
+        }
+        if let Some(addr) = run_to {
+            self.run_to_cursor(addr);
+        }
+    }
+
+    fn breakpoints(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("$");
+            let response: egui::Response =
+                ui.add(egui::TextEdit::singleline(&mut self.breakpoint_input).desired_width(48.0));
+            let submitted: bool =
+                response.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter));
+            if ui.button("Add").clicked() || submitted {
+                if let Ok(addr) = u16::from_str_radix(self.breakpoint_input.trim(), 16) {
+                    self.debugger.add_breakpoint(addr);
+                    self.breakpoint_input.clear();
+                }
+            }
+        });
+        let mut breakpoints: Vec<(u16, bool)> = self.debugger.breakpoints().collect();
+        breakpoints.sort();
+        for (addr, mut enabled) in breakpoints {
+            ui.horizontal(|ui| {
+                if ui
+                    .checkbox(&mut enabled, format!("${:04X}", addr))
+                    .changed()
+                {
+                    self.debugger.set_breakpoint_enabled(addr, enabled);
+                }
+                if ui.small_button("x").clicked() {
+                    self.debugger.remove_breakpoint(addr);
+                }
+            });
+        }
+    }
+
+    fn memory(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Go to $");
+            let response: egui::Response =
+                ui.add(egui::TextEdit::singleline(&mut self.memory_input).desired_width(48.0));
+            if response.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter)) {
+                if let Ok(addr) = u16::from_str_radix(self.memory_input.trim(), 16) {
+                    self.memory_scroll = Some(addr as usize / MEMORY_LINE_SIZE);
+                }
+            }
+        });
+        let row_height: f32 = ui.text_style_height(&TextStyle::Monospace);
+        let mut scroll_area: ScrollArea = ScrollArea::vertical().auto_shrink(false);
+        if let Some(line) = self.memory_scroll.take() {
+            let spacing: f32 = ui.spacing().item_spacing.y;
+            scroll_area = scroll_area.vertical_scroll_offset(line as f32 * (row_height + spacing));
+        }
+        let ram: &RAM = self.nes.ram();
+        scroll_area.show_rows(ui, row_height, 0x10000 / MEMORY_LINE_SIZE, |ui, lines| {
+            for line in lines {
+                let start: u16 = (line * MEMORY_LINE_SIZE) as u16;
+                ui.monospace(hex_dump(ram, start, MEMORY_LINE_SIZE).trim_end());
+            }
+        });
+    }
+}
+
+impl eframe::App for DebuggerApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.update_controllers(ctx);
+        if self.running {
+            self.run_frame();
+            ctx.request_repaint();
+        }
+        egui::SidePanel::left("cpu").show(ctx, |ui| {
+            self.controls(ui);
+            ui.separator();
+            self.registers(ui);
+            ui.separator();
+            ui.heading("Breakpoints");
+            self.breakpoints(ui);
+        });
+        egui::SidePanel::right("memory")
+            .default_width(500.0)
+            .show(ctx, |ui| {
+                ui.heading("Memory");
+                self.memory(ui);
+            });
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal_top(|ui| {
+                self.screen_panel(ui);
+                ui.vertical(|ui| {
+                    ui.heading("Disassembly");
+                    self.disassembly(ui);
+                });
+            });
+        });
+    }
+}
@@ -0,0 +1,193 @@
+// SDL2 frontend: a window showing the frame buffer, the keyboard as both controllers
+// and the APU output on the default audio device
+// Controller 1: arrows, X (A), Z (B), Right Shift (Select), Enter (Start)
+// Controller 2: WASD, K (A), J (B), Tab (Select), Space (Start)
+// R resets the console, P pauses and resumes it and Escape quits
+// With the gamepad feature, the first two gamepads also drive the controllers
+
+use std::{
+    env, thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "gamepad")]
+use nes_emulator::gamepad::Gamepads;
+use nes_emulator::{
+    controller::{Button, KeyMap},
+    nes::{NesBuilder, NES},
+    ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+};
+use sdl2::{
+    audio::{AudioQueue, AudioSpecDesired},
+    event::Event,
+    keyboard::{KeyboardState, Scancode},
+    pixels::PixelFormatEnum,
+    render::{Canvas, Texture, TextureCreator},
+    video::{Window, WindowContext},
+    EventPump,
+};
+
+const SCALE: u32 = 3;
+const SAMPLE_RATE: i32 = 44100;
+// samples queued ahead of the audio device before the oldest ones are skipped
+const MAX_QUEUED_SAMPLES: u32 = SAMPLE_RATE as u32 / 10;
+
+const DEFAULT_KEYS: [(Scancode, u8, Button); 16] = [
+    (Scancode::X, 0, Button::A),
+    (Scancode::Z, 0, Button::B),
+    (Scancode::RShift, 0, Button::Select),
+    (Scancode::Return, 0, Button::Start),
+    (Scancode::Up, 0, Button::Up),
+    (Scancode::Down, 0, Button::Down),
+    (Scancode::Left, 0, Button::Left),
+    (Scancode::Right, 0, Button::Right),
+    (Scancode::K, 1, Button::A),
+    (Scancode::J, 1, Button::B),
+    (Scancode::Tab, 1, Button::Select),
+    (Scancode::Space, 1, Button::Start),
+    (Scancode::W, 1, Button::Up),
+    (Scancode::S, 1, Button::Down),
+    (Scancode::A, 1, Button::Left),
+    (Scancode::D, 1, Button::Right),
+];
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 2 {
+        eprintln!("Usage: cargo run --features sdl --bin nes_sdl -- <rom_file>");
+        std::process::exit(1);
+    }
+    if let Err(err) = run(&args[1]) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(rom_file: &str) -> Result<(), String> {
+    let mut nes: NES = NesBuilder::from_file(rom_file)
+        .trace(false)
+        .sample_rate(SAMPLE_RATE as f64)
+        .build()
+        .map_err(|err| err.to_string())?;
+
+    let sdl: sdl2::Sdl = sdl2::init()?;
+    let window: Window = sdl
+        .video()?
+        .window(
+            "NES",
+            SCREEN_WIDTH as u32 * SCALE,
+            SCREEN_HEIGHT as u32 * SCALE,
+        )
+        .position_centered()
+        .build()
+        .map_err(|err| err.to_string())?;
+    let mut canvas: Canvas<Window> = window
+        .into_canvas()
+        .build()
+        .map_err(|err| err.to_string())?;
+    let texture_creator: TextureCreator<WindowContext> = canvas.texture_creator();
+    // the frame buffer holds R, G, B, A bytes in that order
+    let mut texture: Texture = texture_creator
+        .create_texture_streaming(
+            PixelFormatEnum::RGBA32,
+            SCREEN_WIDTH as u32,
+            SCREEN_HEIGHT as u32,
+        )
+        .map_err(|err| err.to_string())?;
+
+    let audio: AudioQueue<f32> = sdl.audio()?.open_queue(
+        None,
+        &AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        },
+    )?;
+    audio.resume();
+
+    let mut key_map: KeyMap<Scancode> = KeyMap::new();
+    for (scancode, port, button) in DEFAULT_KEYS {
+        key_map.bind(scancode, port, button);
+    }
+
+    #[cfg(feature = "gamepad")]
+    let mut gamepads: Option<Gamepads> = match Gamepads::new() {
+        Ok(gamepads) => Some(gamepads),
+        Err(err) => {
+            eprintln!("{}, running without gamepads", err);
+            None
+        }
+    };
+
+    let mut event_pump: EventPump = sdl.event_pump()?;
+    let frame_duration: Duration = Duration::from_secs_f64(1.0 / nes.region().frames_per_second());
+    let mut next_frame: Instant = Instant::now();
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    scancode: Some(Scancode::Escape),
+                    ..
+                } => break 'running,
+                Event::KeyDown {
+                    scancode: Some(Scancode::R),
+                    repeat: false,
+                    ..
+                } => nes.reset(),
+                Event::KeyDown {
+                    scancode: Some(Scancode::P),
+                    repeat: false,
+                    ..
+                } => {
+                    if nes.is_paused() {
+                        nes.resume();
+                    } else {
+                        nes.pause();
+                    }
+                }
+                _ => {}
+            }
+        }
+        let keyboard: KeyboardState = event_pump.keyboard_state();
+        #[cfg(feature = "gamepad")]
+        if let Some(gamepads) = &mut gamepads {
+            gamepads.update();
+        }
+        for port in 0..2 {
+            #[cfg_attr(not(feature = "gamepad"), allow(unused_mut))]
+            let mut buttons: u8 = key_map
+                .controller_state(port, |&scancode| keyboard.is_scancode_pressed(scancode))
+                .bits();
+            // the keyboard and the gamepad can be used together
+            #[cfg(feature = "gamepad")]
+            if let Some(gamepads) = &gamepads {
+                buttons |= gamepads.controller_state(port).bits();
+            }
+            nes.controller_mut(port).set_buttons(buttons);
+        }
+
+        nes.run_frames(1).map_err(|err| err.to_string())?;
+
+        texture
+            .update(None, nes.ppu().frame_buffer(), SCREEN_WIDTH * 4)
+            .map_err(|err| err.to_string())?;
+        canvas.copy(&texture, None, None)?;
+        canvas.present();
+
+        let samples: Vec<f32> = nes.apu_mut().drain_samples().collect();
+        // skip the samples rather than letting the latency grow
+        if audio.size() / 4 < MAX_QUEUED_SAMPLES {
+            audio.queue_audio(&samples)?;
+        }
+
+        next_frame += frame_duration;
+        let now: Instant = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else {
+            next_frame = now;
+        }
+    }
+    Ok(())
+}
@@ -1,4 +1,7 @@
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+
+// Zeroed by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct Bitfield {
     value: u8,
 }
@@ -8,6 +11,15 @@ impl Bitfield {
         Bitfield { value }
     }
 
+    // Same as new, for when the byte comes from somewhere else, e.g. the stack
+    pub fn from_bits(bits: u8) -> Bitfield {
+        Bitfield::new(bits)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.value
+    }
+
     pub fn get_bit(&self, bit: u8) -> bool {
         (self.value & (1 << bit)) != 0
     }
@@ -20,3 +32,24 @@ impl Bitfield {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits_round_trip_through_the_accessors() {
+        let mut bitfield: Bitfield = Bitfield::from_bits(0b1010_0101);
+        assert_eq!(Bitfield::default().bits(), 0);
+        assert_eq!(bitfield, Bitfield::new(0b1010_0101));
+        assert!(bitfield.get_bit(0));
+        assert!(!bitfield.get_bit(1));
+        assert!(bitfield.get_bit(7));
+        bitfield.set_bit(1, true);
+        bitfield.set_bit(7, false);
+        // setting a bit to its current value changes nothing
+        bitfield.set_bit(0, true);
+        assert_eq!(bitfield.bits(), 0b0010_0111);
+        assert_eq!(Bitfield::from_bits(bitfield.bits()), bitfield);
+    }
+}
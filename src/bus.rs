@@ -0,0 +1,6 @@
+// What the CPU sees of the rest of the system: a 64KB address space where
+// reads may have side effects, e.g. acknowledging an interrupt
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, data: u8);
+}
@@ -0,0 +1,579 @@
+use std::{error::Error, fmt, fs, io, mem, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use self::mmc3::MMC3;
+use crate::mem::{PRG_RAM_START, PRG_ROM_START, TRAINER_START};
+
+mod mmc3;
+
+// Cartridge space as seen by the CPU:
+// 0x6000 - 0x7FFF: 8KB PRG RAM (battery-backed on some cartridges)
+// 0x8000 - 0xFFFF: PRG ROM, and the mapper registers on writes
+// Cartridge space as seen by the PPU:
+// 0x0000 - 0x1FFF: CHR ROM (pattern tables), or CHR RAM on cartridges without CHR ROM
+// Mappers switch which banks of PRG ROM and CHR are visible in these ranges
+
+const PRG_RAM_SIZE: usize = 0x2000;
+pub const HEADER_SIZE: usize = 16;
+pub const TRAINER_SIZE: usize = 512;
+pub const NES_MAGIC: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
+const PRG_PAGE_SIZE: usize = 0x4000;
+const CHR_PAGE_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(io::Error),
+    InvalidMagic,
+    Truncated { expected: usize, actual: usize },
+    // the header declares no PRG ROM, there would be no code to run
+    NoPrgRom,
+    // the header declares a ROM larger than can be addressed
+    InvalidSize,
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Io(err) => write!(f, "Could not read the NES file: {}", err),
+            RomError::InvalidMagic => write!(f, "Invalid NES file"),
+            RomError::Truncated { expected, actual } => write!(
+                f,
+                "Truncated NES file: expected {} bytes, got {}",
+                expected, actual
+            ),
+            RomError::NoPrgRom => write!(f, "Invalid NES file: no PRG ROM"),
+            RomError::InvalidSize => write!(f, "Invalid NES file: ROM size out of range"),
+        }
+    }
+}
+
+impl Error for RomError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    // the cartridge has 2KB of extra VRAM, for 4 independent nametables
+    FourScreen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+}
+
+impl Region {
+    // CPU cycles in one video frame: 341 * 262 / 3 PPU dots on NTSC,
+    // 341 * 312 / 3.2 on PAL
+    pub fn cycles_per_frame(&self) -> u64 {
+        match self {
+            Region::Ntsc => 29781,
+            Region::Pal => 33247,
+        }
+    }
+
+    pub fn cpu_clock_rate(&self) -> f64 {
+        match self {
+            Region::Ntsc => 1_789_773.0,
+            Region::Pal => 1_662_607.0,
+        }
+    }
+
+    pub fn frames_per_second(&self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0988,
+            Region::Pal => 50.007,
+        }
+    }
+}
+
+// The 16-byte iNES or NES 2.0 header at the start of a ROM, sizes are in bytes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    pub is_nes2: bool,
+    pub mapper: u16,
+    // NES 2.0 only, 0 for iNES
+    pub submapper: u8,
+    // number of 16KB PRG ROM and 8KB CHR ROM pages, rounded up for the NES 2.0
+    // exponent sizes
+    pub prg_rom_banks: u16,
+    pub chr_rom_banks: u16,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    // volatile and battery-backed PRG RAM; iNES ROMs get 8KB of volatile PRG RAM
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    // volatile and battery-backed CHR RAM; iNES ROMs get 8KB of CHR RAM if
+    // they have no CHR ROM
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub mirroring: Mirroring,
+    pub has_battery: bool,
+    pub has_trainer: bool,
+    pub region: Region,
+}
+
+impl Header {
+    // Parses the header at the start of a whole ROM
+    pub fn from_rom(data: &[u8]) -> Result<Header, RomError> {
+        match data.get(..HEADER_SIZE) {
+            Some(header) => Header::parse(header.try_into().unwrap()),
+            None => Err(RomError::Truncated {
+                expected: HEADER_SIZE,
+                actual: data.len(),
+            }),
+        }
+    }
+
+    pub fn parse(header: &[u8; HEADER_SIZE]) -> Result<Header, RomError> {
+        if header[..4] != NES_MAGIC {
+            return Err(RomError::InvalidMagic);
+        }
+        let prg_rom_size: usize;
+        let chr_rom_size: usize;
+        let mut submapper: u8 = 0;
+        let prg_ram_size: usize;
+        let mut prg_nvram_size: usize = 0;
+        let chr_ram_size: usize;
+        let mut chr_nvram_size: usize = 0;
+        let region: Region;
+        let is_nes2: bool = (header[7] & 0x0C) == 0x08;
+        let has_battery: bool = (header[6] & 0b00000010) != 0;
+        let has_trainer: bool = (header[6] & 0b00000100) != 0;
+        let mirroring: Mirroring = if (header[6] & 0b00001000) != 0 {
+            Mirroring::FourScreen
+        } else if (header[6] & 0b00000001) != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        let mut mapper: u16 = (header[7] & 0xF0) as u16 | (header[6] >> 4) as u16;
+        if is_nes2 {
+            mapper |= ((header[8] & 0b1111) as u16) << 8;
+            submapper = header[8] >> 4;
+            prg_rom_size = Header::rom_size(header[9] & 0b1111, header[4], PRG_PAGE_SIZE)?;
+            chr_rom_size = Header::rom_size(header[9] >> 4, header[5], CHR_PAGE_SIZE)?;
+            prg_ram_size = Header::ram_size(header[10] & 0b1111);
+            prg_nvram_size = Header::ram_size(header[10] >> 4);
+            chr_ram_size = Header::ram_size(header[11] & 0b1111);
+            chr_nvram_size = Header::ram_size(header[11] >> 4);
+            // 0: NTSC, 1: PAL, 2: multiple regions, 3: Dendy (PAL timings)
+            region = match header[12] & 0b11 {
+                1 | 3 => Region::Pal,
+                _ => Region::Ntsc,
+            };
+        } else {
+            prg_rom_size = header[4] as usize * PRG_PAGE_SIZE;
+            chr_rom_size = header[5] as usize * CHR_PAGE_SIZE;
+            prg_ram_size = PRG_RAM_SIZE;
+            chr_ram_size = if chr_rom_size == 0 { CHR_RAM_SIZE } else { 0 };
+            region = if header[9] & 1 != 0 {
+                Region::Pal
+            } else {
+                Region::Ntsc
+            };
+        }
+        Ok(Header {
+            is_nes2,
+            mapper,
+            submapper,
+            prg_rom_banks: Header::bank_count(prg_rom_size, PRG_PAGE_SIZE)?,
+            chr_rom_banks: Header::bank_count(chr_rom_size, CHR_PAGE_SIZE)?,
+            prg_rom_size,
+            chr_rom_size,
+            prg_ram_size,
+            prg_nvram_size,
+            chr_ram_size,
+            chr_nvram_size,
+            mirroring,
+            has_battery,
+            has_trainer,
+            region,
+        })
+    }
+
+    // Checks that a ROM of file_len bytes holds everything the header declares:
+    // the header, the trainer, the PRG ROM and the CHR ROM; extra bytes at the
+    // end are allowed
+    pub fn validate(&self, file_len: usize) -> Result<(), RomError> {
+        if self.prg_rom_size == 0 {
+            return Err(RomError::NoPrgRom);
+        }
+        let expected: usize = self.file_size().ok_or(RomError::InvalidSize)?;
+        if file_len < expected {
+            return Err(RomError::Truncated {
+                expected,
+                actual: file_len,
+            });
+        }
+        Ok(())
+    }
+
+    // Size of the ROM file up to the end of the CHR ROM, None if it does not
+    // fit in a usize
+    fn file_size(&self) -> Option<usize> {
+        let trainer_size: usize = if self.has_trainer { TRAINER_SIZE } else { 0 };
+        (HEADER_SIZE + trainer_size)
+            .checked_add(self.prg_rom_size)?
+            .checked_add(self.chr_rom_size)
+    }
+
+    // NES 2.0 ROM sizes are a number of pages, the most significant nibble
+    // coming from byte 9; a nibble of 0xF means the least significant byte is
+    // EEEEEEMM, for a size of 2^E * (MM * 2 + 1) bytes, which overflows for
+    // the largest exponents
+    fn rom_size(msb: u8, lsb: u8, page_size: usize) -> Result<usize, RomError> {
+        let size: Option<usize> = if msb == 0xF {
+            1usize
+                .checked_shl((lsb >> 2) as u32)
+                .and_then(|size| size.checked_mul((lsb & 0b11) as usize * 2 + 1))
+        } else {
+            Some(((msb as usize) << 8 | lsb as usize) * page_size)
+        };
+        size.ok_or(RomError::InvalidSize)
+    }
+
+    // Number of pages of a ROM size, rounded up; the exponent sizes can give
+    // more than the mappers can address
+    fn bank_count(size: usize, page_size: usize) -> Result<u16, RomError> {
+        u16::try_from(size.div_ceil(page_size)).map_err(|_| RomError::InvalidSize)
+    }
+
+    // NES 2.0 RAM sizes are shift counts: 0 is no RAM, otherwise 64 << n bytes
+    fn ram_size(shift: u8) -> usize {
+        if shift == 0 {
+            0
+        } else {
+            64 << shift
+        }
+    }
+
+    // Mirroring the cartridge powers on with; mappers that control mirroring
+    // may change it at runtime
+    pub fn initial_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+// Bank switching hardware of the cartridge
+#[derive(Debug, Serialize, Deserialize)]
+enum Mapper {
+    // mapper 0, no bank switching; the mappers not supported yet run as NROM
+    NROM,
+    MMC3(MMC3),
+}
+
+// Only the mutable state is part of save states, the ROM data is taken back
+// from the running cartridge when loading one
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cartridge {
+    header: Header,
+    mapper: Mapper,
+    #[serde(skip)]
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    #[serde(skip)]
+    chr_rom: Vec<u8>,
+    // empty if the cartridge has only CHR ROM
+    chr_ram: Vec<u8>,
+    #[serde(skip)]
+    save_path: Option<PathBuf>,
+}
+
+impl Cartridge {
+    pub fn new(
+        header: Header,
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        save_path: Option<PathBuf>,
+    ) -> Cartridge {
+        let mut prg_ram: Vec<u8> = vec![0; header.prg_ram_size + header.prg_nvram_size];
+        if header.has_battery {
+            if let Some(data) = save_path.as_ref().and_then(|path| fs::read(path).ok()) {
+                let len: usize = data.len().min(prg_ram.len());
+                prg_ram[..len].copy_from_slice(&data[..len]);
+            }
+        }
+        let chr_ram: Vec<u8> = vec![0; header.chr_ram_size + header.chr_nvram_size];
+        let mapper: Mapper = match header.mapper {
+            4 => Mapper::MMC3(MMC3::new(header.mirroring)),
+            _ => Mapper::NROM,
+        };
+        Cartridge {
+            header,
+            mapper,
+            prg_rom,
+            prg_ram,
+            chr_rom,
+            chr_ram,
+            save_path,
+        }
+    }
+
+    // Copies the 512-byte trainer of the ROM to 0x7000 - 0x71FF, where the
+    // games that have one expect it
+    pub fn load_trainer(&mut self, trainer: &[u8]) {
+        for (i, &byte) in trainer.iter().enumerate() {
+            self.write(TRAINER_START + i as u16, byte);
+        }
+    }
+
+    // Moves the data that is not part of save states from the running cartridge
+    pub fn restore_rom(&mut self, running: &mut Cartridge) {
+        mem::swap(&mut self.prg_rom, &mut running.prg_rom);
+        mem::swap(&mut self.chr_rom, &mut running.chr_rom);
+        mem::swap(&mut self.save_path, &mut running.save_path);
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    // PRG RAM smaller than 8KB is mirrored, cartridges without any read 0
+    fn prg_ram_index(&self, addr: u16) -> Option<usize> {
+        if self.prg_ram.is_empty() {
+            None
+        } else {
+            Some((addr - PRG_RAM_START) as usize % self.prg_ram.len())
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            PRG_RAM_START..PRG_ROM_START => self
+                .prg_ram_index(addr)
+                .map_or(0, |index| self.prg_ram[index]),
+            PRG_ROM_START.. => match &self.mapper {
+                // 16KB PRG ROMs are mirrored in 0xC000 - 0xFFFF
+                Mapper::NROM => self.prg_rom[(addr - PRG_ROM_START) as usize % self.prg_rom.len()],
+                Mapper::MMC3(mmc3) => self.prg_rom[mmc3.prg_rom_address(addr, self.prg_rom.len())],
+            },
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            PRG_RAM_START..PRG_ROM_START => {
+                if let Some(index) = self.prg_ram_index(addr) {
+                    self.prg_ram[index] = data;
+                }
+            }
+            PRG_ROM_START.. => {
+                if let Mapper::MMC3(mmc3) = &mut self.mapper {
+                    mmc3.write_register(addr, data);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Offset in the CHR ROM or RAM of a PPU address in 0x0000 - 0x1FFF
+    fn chr_address(&self, addr: u16) -> usize {
+        match &self.mapper {
+            Mapper::NROM => addr as usize,
+            Mapper::MMC3(mmc3) => mmc3.chr_address(addr),
+        }
+    }
+
+    pub fn chr_read(&self, addr: u16) -> u8 {
+        let addr: usize = self.chr_address(addr);
+        if !self.chr_ram.is_empty() {
+            return self.chr_ram[addr % self.chr_ram.len()];
+        }
+        if self.chr_rom.is_empty() {
+            return 0;
+        }
+        self.chr_rom[addr % self.chr_rom.len()]
+    }
+
+    // Writes are ignored on CHR ROM
+    pub fn chr_write(&mut self, addr: u16, data: u8) {
+        let addr: usize = self.chr_address(addr);
+        if !self.chr_ram.is_empty() {
+            let len: usize = self.chr_ram.len();
+            self.chr_ram[addr % len] = data;
+        }
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        match &self.mapper {
+            Mapper::NROM => self.header.mirroring,
+            Mapper::MMC3(mmc3) => mmc3.mirroring(),
+        }
+    }
+
+    // Called by the PPU when its address line A12 rises, which happens once
+    // per scanline while rendering with the background and the sprites using
+    // different pattern tables
+    pub fn notify_a12_rise(&mut self) {
+        if let Mapper::MMC3(mmc3) = &mut self.mapper {
+            mmc3.clock_irq_counter();
+        }
+    }
+
+    // Level of the cartridge IRQ line
+    pub fn irq(&self) -> bool {
+        match &self.mapper {
+            Mapper::NROM => false,
+            Mapper::MMC3(mmc3) => mmc3.irq(),
+        }
+    }
+
+    // Flushes the PRG RAM to the .sav file if the cartridge is battery-backed
+    pub fn save(&self) -> io::Result<()> {
+        match &self.save_path {
+            Some(path) if self.header.has_battery => fs::write(path, &self.prg_ram),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, process};
+
+    use super::*;
+    use crate::test_rom;
+
+    #[test]
+    fn battery_backed_prg_ram_round_trips_through_the_save_file() {
+        let path: PathBuf = env::temp_dir().join(format!("nes_prg_ram_{}.sav", process::id()));
+        let header: Header = Header::parse(&test_rom::header(1, 1, 0b10)).unwrap();
+        let mut cartridge: Cartridge = Cartridge::new(
+            header.clone(),
+            vec![0; 0x4000],
+            vec![0; 0x2000],
+            Some(path.clone()),
+        );
+        cartridge.write(0x6000, 0x12);
+        cartridge.write(0x7FFF, 0x34);
+        cartridge.save().unwrap();
+
+        let reloaded: Cartridge =
+            Cartridge::new(header, vec![0; 0x4000], vec![0; 0x2000], Some(path.clone()));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(reloaded.read(0x6000), 0x12);
+        assert_eq!(reloaded.read(0x7FFF), 0x34);
+    }
+
+    #[test]
+    fn vertical_mirroring_header_reports_vertical_initial_mirroring() {
+        let header: Header = Header::parse(&test_rom::header(1, 1, 0b1)).unwrap();
+        assert_eq!(header.initial_mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn chr_ram_writes_read_back_and_chr_rom_ignores_them() {
+        let mut cartridge: Cartridge = test_rom::cartridge(0);
+        cartridge.chr_write(0x0000, 0x12);
+        cartridge.chr_write(0x1FFF, 0x34);
+        assert_eq!(cartridge.chr_read(0x0000), 0x12);
+        assert_eq!(cartridge.chr_read(0x1FFF), 0x34);
+
+        let header: Header = Header::parse(&test_rom::header(1, 1, 0)).unwrap();
+        let mut cartridge: Cartridge =
+            Cartridge::new(header, vec![0; 0x4000], vec![0x56; 0x2000], None);
+        cartridge.chr_write(0x0000, 0x12);
+        assert_eq!(cartridge.chr_read(0x0000), 0x56);
+    }
+
+    #[test]
+    fn ines_header_fields_are_parsed() {
+        let mut bytes: [u8; HEADER_SIZE] = test_rom::header(2, 0, 0x42);
+        // mapper high nibble, PAL
+        bytes[7] = 0x10;
+        bytes[9] = 0x01;
+        let header: Header = Header::parse(&bytes).unwrap();
+        assert!(!header.is_nes2);
+        assert_eq!((header.mapper, header.submapper), (0x14, 0));
+        assert_eq!((header.prg_rom_banks, header.chr_rom_banks), (2, 0));
+        assert_eq!((header.prg_rom_size, header.chr_rom_size), (0x8000, 0));
+        // 8KB of PRG RAM, and of CHR RAM without CHR ROM
+        assert_eq!((header.prg_ram_size, header.chr_ram_size), (0x2000, 0x2000));
+        assert_eq!(header.mirroring, Mirroring::Horizontal);
+        assert!(header.has_battery);
+        assert!(!header.has_trainer);
+        assert_eq!(header.region, Region::Pal);
+        assert!(matches!(
+            Header::parse(&[0; HEADER_SIZE]),
+            Err(RomError::InvalidMagic)
+        ));
+    }
+
+    // NES 2.0 header with the given bytes 4 - 15
+    fn nes2_header(bytes: [u8; 12]) -> [u8; HEADER_SIZE] {
+        let mut header: [u8; HEADER_SIZE] = test_rom::header(0, 0, 0);
+        header[4..].copy_from_slice(&bytes);
+        header
+    }
+
+    #[test]
+    fn nes2_header_fields_are_parsed() {
+        // mapper 0x104 submapper 2, vertical mirroring, 8KB of battery-backed
+        // PRG RAM, 8KB of CHR RAM, PAL
+        let header: Header = Header::parse(&nes2_header([
+            2, 1, 0x41, 0x08, 0x21, 0x00, 0x70, 0x07, 0x01, 0, 0, 0,
+        ]))
+        .unwrap();
+        assert!(header.is_nes2);
+        assert_eq!((header.mapper, header.submapper), (0x104, 2));
+        assert_eq!((header.prg_rom_size, header.chr_rom_size), (0x8000, 0x2000));
+        assert_eq!((header.prg_ram_size, header.prg_nvram_size), (0, 0x2000));
+        assert_eq!((header.chr_ram_size, header.chr_nvram_size), (0x2000, 0));
+        assert_eq!(header.mirroring, Mirroring::Vertical);
+        assert_eq!(header.region, Region::Pal);
+    }
+
+    #[test]
+    fn nes2_exponent_sizes_are_checked() {
+        // 2^14 * (1 * 2 + 1)
+        let header: Header = Header::parse(&nes2_header([
+            14 << 2 | 1,
+            0,
+            0,
+            0x08,
+            0,
+            0x0F,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ]))
+        .unwrap();
+        assert_eq!((header.prg_rom_size, header.prg_rom_banks), (49152, 3));
+        // 2^63 * 3 overflows, 2^63 is more pages than a mapper can address
+        for lsb in [0xFD, 0xFC] {
+            let header: [u8; HEADER_SIZE] =
+                nes2_header([lsb, 0, 0, 0x08, 0, 0x0F, 0, 0, 0, 0, 0, 0]);
+            assert!(matches!(Header::parse(&header), Err(RomError::InvalidSize)));
+        }
+    }
+
+    #[test]
+    fn validate_reports_roms_smaller_than_their_header_declares() {
+        let header: Header = Header::parse(&test_rom::header(2, 1, 0)).unwrap();
+        let expected: usize = HEADER_SIZE + 0x8000 + 0x2000;
+        assert!(header.validate(expected).is_ok());
+        assert!(matches!(
+            header.validate(HEADER_SIZE + 0x4000),
+            Err(RomError::Truncated { expected: size, actual: 0x4010 }) if size == expected
+        ));
+    }
+
+    #[test]
+    fn validate_reports_sizes_that_overflow() {
+        let mut header: Header = Header::parse(&test_rom::header(1, 1, 0)).unwrap();
+        header.prg_rom_size = usize::MAX;
+        assert!(matches!(
+            header.validate(usize::MAX),
+            Err(RomError::InvalidSize)
+        ));
+    }
+}
@@ -0,0 +1,234 @@
+use serde::{Deserialize, Serialize};
+
+use super::Mirroring;
+
+// MMC3 (mapper 4) registers, as seen by the CPU; each range holds two
+// registers, one at the even addresses and one at the odd ones:
+// 0x8000: bank select, index of the bank register written by 0x8001 in
+//         bits 0 - 2, PRG ROM mode in bit 6 and CHR inversion in bit 7
+// 0x8001: bank data
+// 0xA000: mirroring, 0 for vertical and 1 for horizontal
+// 0xA001: PRG RAM protect, not emulated
+// 0xC000: IRQ latch, the value the counter is reloaded with
+// 0xC001: IRQ reload, the counter is reloaded on its next clock
+// 0xE000: IRQ disable, also acknowledges a pending IRQ
+// 0xE001: IRQ enable
+//
+// PRG ROM, in 8KB banks, with the PRG ROM mode 0 or 1:
+// 0x8000 - 0x9FFF: R6 or the second to last bank
+// 0xA000 - 0xBFFF: R7
+// 0xC000 - 0xDFFF: the second to last bank or R6
+// 0xE000 - 0xFFFF: last bank
+// CHR, in 1KB banks, with the CHR inversion 0 or 1:
+// 0x0000 - 0x07FF: R0, a 2KB bank whose lowest bit is ignored, or R2 - R3
+// 0x0800 - 0x0FFF: R1, a 2KB bank as well, or R4 - R5
+// 0x1000 - 0x17FF: R2 - R3 or R0
+// 0x1800 - 0x1FFF: R4 - R5 or R1
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+// bits of the bank select register
+const PRG_ROM_MODE: u8 = 0b01000000;
+const CHR_INVERSION: u8 = 0b10000000;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MMC3 {
+    bank_select: u8,
+    // R0 - R7
+    banks: [u8; 8],
+    mirroring: Mirroring,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+}
+
+impl MMC3 {
+    pub fn new(mirroring: Mirroring) -> MMC3 {
+        MMC3 {
+            bank_select: 0,
+            banks: [0; 8],
+            mirroring,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match (addr, addr & 1) {
+            (0x8000..=0x9FFF, 0) => self.bank_select = data,
+            (0x8000..=0x9FFF, _) => self.banks[(self.bank_select & 0b111) as usize] = data,
+            // four-screen cartridges have their own nametables
+            (0xA000..=0xBFFF, 0) if self.mirroring != Mirroring::FourScreen => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            (0xA000..=0xBFFF, _) => {}
+            (0xC000..=0xDFFF, 0) => self.irq_latch = data,
+            (0xC000..=0xDFFF, _) => self.irq_reload = true,
+            (0xE000..=0xFFFF, 0) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            (0xE000..=0xFFFF, _) => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    // Offset in the PRG ROM of a CPU address in 0x8000 - 0xFFFF; a PRG ROM
+    // of a single bank fills every window, and one smaller than a bank is
+    // mirrored inside it
+    pub fn prg_rom_address(&self, addr: u16, prg_rom_size: usize) -> usize {
+        let bank_count: usize = (prg_rom_size / PRG_BANK_SIZE).max(1);
+        let r6: usize = (self.banks[6] & 0b00111111) as usize;
+        // the PRG ROM mode swaps 0x8000 and 0xC000
+        let swapped: bool = self.bank_select & PRG_ROM_MODE != 0;
+        let bank: usize = match addr {
+            0x8000..=0x9FFF if swapped => bank_count.saturating_sub(2),
+            0x8000..=0x9FFF => r6,
+            0xA000..=0xBFFF => (self.banks[7] & 0b00111111) as usize,
+            0xC000..=0xDFFF if swapped => r6,
+            0xC000..=0xDFFF => bank_count.saturating_sub(2),
+            _ => bank_count - 1,
+        };
+        ((bank % bank_count) * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE) % prg_rom_size.max(1)
+    }
+
+    // Offset in the CHR ROM or RAM of a PPU address in 0x0000 - 0x1FFF
+    pub fn chr_address(&self, addr: u16) -> usize {
+        // the CHR inversion swaps the two pattern tables
+        let addr: u16 = if self.bank_select & CHR_INVERSION != 0 {
+            addr ^ 0x1000
+        } else {
+            addr
+        };
+        let bank: usize = match addr {
+            0x0000..=0x07FF => (self.banks[0] & !1) as usize + (addr >= 0x0400) as usize,
+            0x0800..=0x0FFF => (self.banks[1] & !1) as usize + (addr >= 0x0C00) as usize,
+            _ => self.banks[2 + (addr as usize - 0x1000) / CHR_BANK_SIZE] as usize,
+        };
+        bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    // Clocked once per scanline by the rise of the PPU address line A12; the
+    // IRQ fires when the counter reaches zero
+    pub fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq_pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn irq_counter_reloads_from_the_latch_and_fires_at_zero() {
+        let mut mmc3: MMC3 = MMC3::new(Mirroring::Vertical);
+        mmc3.write_register(0xC000, 2);
+        mmc3.write_register(0xC001, 0);
+        mmc3.write_register(0xE001, 0);
+        let mut irqs: Vec<bool> = Vec::new();
+        for _ in 0..6 {
+            mmc3.clock_irq_counter();
+            irqs.push(mmc3.irq());
+            // acknowledged and enabled again right away
+            mmc3.write_register(0xE000, 0);
+            mmc3.write_register(0xE001, 0);
+        }
+        // 2 on reload, then 1, 0, and 2 again when clocked at 0
+        assert_eq!(irqs, [false, false, true, false, false, true]);
+    }
+
+    #[test]
+    fn disabled_irq_does_not_fire() {
+        let mut mmc3: MMC3 = MMC3::new(Mirroring::Vertical);
+        mmc3.write_register(0xC000, 1);
+        mmc3.write_register(0xC001, 0);
+        for _ in 0..4 {
+            mmc3.clock_irq_counter();
+            assert!(!mmc3.irq());
+        }
+    }
+
+    // Sets R0 - R7 through the bank select and bank data registers, with the
+    // given mode bits in bank select
+    fn set_banks(mmc3: &mut MMC3, mode: u8, banks: [u8; 8]) {
+        for (index, bank) in banks.into_iter().enumerate() {
+            mmc3.write_register(0x8000, mode | index as u8);
+            mmc3.write_register(0x8001, bank);
+        }
+    }
+
+    #[test]
+    fn prg_rom_mode_swaps_0x8000_and_0xc000() {
+        let prg_rom_size: usize = 8 * PRG_BANK_SIZE;
+        let mut mmc3: MMC3 = MMC3::new(Mirroring::Vertical);
+        for (mode, banks) in [(0, [3, 5, 6, 7]), (PRG_ROM_MODE, [6, 5, 3, 7])] {
+            set_banks(&mut mmc3, mode, [0, 0, 0, 0, 0, 0, 3, 5]);
+            let addresses: Vec<usize> = [0x8000, 0xA000, 0xC000, 0xE000]
+                .into_iter()
+                .map(|addr| mmc3.prg_rom_address(addr + 0x12, prg_rom_size))
+                .collect();
+            let expected: Vec<usize> = banks
+                .into_iter()
+                .map(|bank| bank * PRG_BANK_SIZE + 0x12)
+                .collect();
+            assert_eq!(addresses, expected);
+        }
+    }
+
+    #[test]
+    fn chr_inversion_swaps_the_pattern_tables() {
+        let mut mmc3: MMC3 = MMC3::new(Mirroring::Vertical);
+        for (mode, banks) in [
+            (0, [4, 5, 6, 7, 10, 11, 12, 13]),
+            (CHR_INVERSION, [10, 11, 12, 13, 4, 5, 6, 7]),
+        ] {
+            // the lowest bit of the 2KB banks R0 and R1 is ignored
+            set_banks(&mut mmc3, mode, [5, 7, 10, 11, 12, 13, 0, 0]);
+            let addresses: Vec<usize> = (0..8)
+                .map(|bank| mmc3.chr_address(bank * CHR_BANK_SIZE as u16 + 0x12))
+                .collect();
+            let expected: Vec<usize> = banks
+                .into_iter()
+                .map(|bank| bank * CHR_BANK_SIZE + 0x12)
+                .collect();
+            assert_eq!(addresses, expected);
+        }
+    }
+
+    #[test]
+    fn prg_rom_smaller_than_two_banks_fills_every_window() {
+        let mut mmc3: MMC3 = MMC3::new(Mirroring::Vertical);
+        for mode in [0, PRG_ROM_MODE] {
+            set_banks(&mut mmc3, mode, [0, 0, 0, 0, 0, 0, 3, 5]);
+            for addr in [0x8000, 0xA000, 0xC000, 0xE000] {
+                assert_eq!(mmc3.prg_rom_address(addr + 0x12, PRG_BANK_SIZE), 0x12);
+                assert_eq!(mmc3.prg_rom_address(addr + 0x1012, 0x1000), 0x12);
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use std::{cell::Cell, collections::HashMap, hash::Hash};
+
+use serde::{Deserialize, Serialize};
+
+// Buttons in the order they are shifted out of the controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    A = 0,
+    B = 1,
+    Select = 2,
+    Start = 3,
+    Up = 4,
+    Down = 5,
+    Left = 6,
+    Right = 7,
+}
+
+// Buttons held on a controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ControllerState {
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl ControllerState {
+    pub fn set(&mut self, button: Button, pressed: bool) {
+        let field: &mut bool = match button {
+            Button::A => &mut self.a,
+            Button::B => &mut self.b,
+            Button::Select => &mut self.select,
+            Button::Start => &mut self.start,
+            Button::Up => &mut self.up,
+            Button::Down => &mut self.down,
+            Button::Left => &mut self.left,
+            Button::Right => &mut self.right,
+        };
+        *field = pressed;
+    }
+
+    // One bit per button, bit 0 being A
+    pub fn bits(&self) -> u8 {
+        self.a as u8
+            | (self.b as u8) << Button::B as u8
+            | (self.select as u8) << Button::Select as u8
+            | (self.start as u8) << Button::Start as u8
+            | (self.up as u8) << Button::Up as u8
+            | (self.down as u8) << Button::Down as u8
+            | (self.left as u8) << Button::Left as u8
+            | (self.right as u8) << Button::Right as u8
+    }
+}
+
+// Bindings from frontend keys (keyboard scancodes, gamepad buttons...) to
+// the buttons of the two controllers
+#[derive(Debug, Clone, Default)]
+pub struct KeyMap<K: Eq + Hash> {
+    bindings: HashMap<K, (u8, Button)>,
+}
+
+impl<K: Eq + Hash> KeyMap<K> {
+    pub fn new() -> KeyMap<K> {
+        KeyMap {
+            bindings: HashMap::new(),
+        }
+    }
+
+    // A key drives a single button, binding it again replaces the previous binding
+    pub fn bind(&mut self, key: K, port: u8, button: Button) {
+        self.bindings.insert(key, (port, button));
+    }
+
+    pub fn unbind(&mut self, key: &K) {
+        self.bindings.remove(key);
+    }
+
+    pub fn get(&self, key: &K) -> Option<(u8, Button)> {
+        self.bindings.get(key).copied()
+    }
+
+    // Buttons of the controller in port that have one of their keys pressed
+    pub fn controller_state(&self, port: u8, is_pressed: impl Fn(&K) -> bool) -> ControllerState {
+        let mut state: ControllerState = ControllerState::default();
+        for (key, &(key_port, button)) in &self.bindings {
+            if key_port == port && is_pressed(key) {
+                state.set(button, true);
+            }
+        }
+        state
+    }
+}
+
+// Standard controller, read one button at a time through a shift register
+// Writing 1 then 0 to 0x4016 latches the buttons of both controllers, each read
+// of 0x4016 (port 0) or 0x4017 (port 1) then returns the next one
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Controller {
+    buttons: u8,
+    strobe: bool,
+    // a Cell since reading shifts the register
+    shift_register: Cell<u8>,
+}
+
+impl Controller {
+    pub fn new() -> Controller {
+        Controller::default()
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if pressed {
+            self.buttons |= 1 << button as u8;
+        } else {
+            self.buttons &= !(1 << button as u8);
+        }
+    }
+
+    // One bit per button, bit 0 being A
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons;
+    }
+
+    pub fn set_state(&mut self, state: ControllerState) {
+        self.buttons = state.bits();
+    }
+
+    pub fn buttons(&self) -> u8 {
+        self.buttons
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 1 != 0;
+        if self.strobe {
+            self.shift_register.set(self.buttons);
+        }
+    }
+
+    // Official controllers return 1 once all 8 buttons have been read
+    pub fn read(&self) -> u8 {
+        if self.strobe {
+            return self.buttons & 1;
+        }
+        let shift_register: u8 = self.shift_register.get();
+        self.shift_register.set(shift_register >> 1 | 0b10000000);
+        shift_register & 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mem::{JOY1, JOY2},
+        ram::RAM,
+        test_rom,
+    };
+
+    #[test]
+    fn each_port_shifts_out_its_own_buttons() {
+        let mut ram: RAM = RAM::from_bytes(&test_rom::nrom(&[])).unwrap();
+        let mut first: ControllerState = ControllerState::default();
+        first.set(Button::A, true);
+        first.set(Button::Start, true);
+        first.set(Button::Right, true);
+        let mut second: ControllerState = ControllerState::default();
+        second.set(Button::B, true);
+        second.set(Button::Up, true);
+        ram.controller_mut(0).set_state(first);
+        ram.controller_mut(1).set_state(second);
+
+        ram.write(JOY1, 1);
+        ram.write(JOY1, 0);
+        let mut joy1: Vec<u8> = Vec::new();
+        let mut joy2: Vec<u8> = Vec::new();
+        for _ in 0..10 {
+            joy1.push(ram.read(JOY1) & 1);
+            joy2.push(ram.read(JOY2) & 1);
+        }
+        // A, B, Select, Start, Up, Down, Left, Right then 1s
+        assert_eq!(joy1, [1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+        assert_eq!(joy2, [0, 1, 0, 0, 1, 0, 0, 0, 1, 1]);
+    }
+}
@@ -1,13 +1,30 @@
-use crate::{bitfield::Bitfield, ram::RAM};
+use core::{error::Error, fmt};
 
-#[derive(Debug)]
-enum AddressingMode {
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bitfield::Bitfield,
+    bus::Bus,
+    mem::{IRQ_VECTOR, NMI_VECTOR, RESET_VECTOR, STACK_BASE},
+};
+
+use self::opcodes::{Opcode, OPCODES};
+
+mod opcodes;
+
+// bits of the status register that only exist on the stack
+const BREAK_FLAG: u8 = 1 << 4;
+const UNUSED_FLAG: u8 = 1 << 5;
+const INTERRUPT_CYCLES: u64 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingMode {
     Accumulator,
     Absolute,
     AbsoluteX,
     AbsoluteY,
     Immediate,
-    // Implied is a placeholder for instructions that don't require an operand,
+    Implied,
     Indirect,
     IndexedIndirect, // (Indirect, X)
     IndirectIndexed, // (Indirect), Y
@@ -17,13 +34,70 @@ enum AddressingMode {
     ZeroPageY,
 }
 
+impl AddressingMode {
+    // Number of operand bytes following the opcode
+    fn operand_length(&self) -> u16 {
+        match self {
+            AddressingMode::Accumulator | AddressingMode::Implied => 0,
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect => 2,
+            _ => 1,
+        }
+    }
+}
+
+// Mnemonics of the instructions documented by MOS, the other ones being
+// side effects of the instruction decoder
+const OFFICIAL_MNEMONICS: [&str; 56] = [
+    "ADC", "AND", "ASL", "BCC", "BCS", "BEQ", "BIT", "BMI", "BNE", "BPL", "BRK", "BVC", "BVS",
+    "CLC", "CLD", "CLI", "CLV", "CMP", "CPX", "CPY", "DEC", "DEX", "DEY", "EOR", "INC", "INX",
+    "INY", "JMP", "JSR", "LDA", "LDX", "LDY", "LSR", "NOP", "ORA", "PHA", "PHP", "PLA", "PLP",
+    "ROL", "ROR", "RTI", "RTS", "SBC", "SEC", "SED", "SEI", "STA", "STX", "STY", "TAX", "TAY",
+    "TSX", "TXA", "TXS", "TYA",
+];
+
+// What an opcode does, known without executing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    // opcode and operand bytes
+    pub length: u8,
+    // without the page crossing and branch penalties
+    pub cycles: u64,
+    pub page_cross_penalty: bool,
+    pub is_official: bool,
+}
+
+// Metadata of an opcode, None for the ones the CPU does not implement
+pub fn opcode_info(opcode: u8) -> Option<OpcodeInfo> {
+    let instruction: Opcode = OPCODES[opcode as usize]?;
+    // 0xEA is the only official NOP and 0xE9 the only official SBC #imm
+    let is_official: bool = OFFICIAL_MNEMONICS.contains(&instruction.mnemonic)
+        && !(instruction.mnemonic == "NOP" && opcode != 0xEA)
+        && opcode != 0xEB;
+    Some(OpcodeInfo {
+        opcode,
+        mnemonic: instruction.mnemonic,
+        mode: instruction.mode,
+        length: 1 + instruction.mode.operand_length() as u8,
+        cycles: instruction.cycles,
+        page_cross_penalty: instruction.page_cross_penalty,
+        is_official,
+    })
+}
+
 enum Register {
     A,
     X,
     Y,
 }
 
-enum StatusFlag {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusFlag {
     Carry = 0,
     Zero = 1,
     InterruptDisable = 2,
@@ -32,7 +106,47 @@ enum StatusFlag {
     Negative = 7,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuError {
+    // A JAM (KIL) opcode locked up the CPU at this address
+    Jammed(u16),
+    UnknownOpcode { opcode: u8, addr: u16 },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::Jammed(addr) => write!(f, "CPU jammed at ${:04X}", addr),
+            CpuError::UnknownOpcode { opcode, addr } => {
+                write!(f, "Unknown opcode: {:#X} at ${:04X}", opcode, addr)
+            }
+        }
+    }
+}
+
+impl Error for CpuError {}
+
+// Values of the CPU registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CpuState {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub pc: u16,
+    pub s: u8,
+    pub p: u8,
+}
+
+// Addresses stored in the interrupt vectors at the top of memory; BRK uses
+// the IRQ one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vectors {
+    pub nmi: u16,
+    pub reset: u16,
+    pub irq: u16,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CPU {
     a: u8, // Accumulator
     x: u8,
@@ -40,33 +154,149 @@ pub struct CPU {
     pc: u16,     // Program Counter
     s: u8,       // Stack Pointer
     p: Bitfield, // Status register
+    // set when resolving the operand address crossed a page boundary
+    page_crossed: bool,
+    // cycles taken by the current instruction on top of its base count
+    extra_cycles: u64,
+    // set by the JAM opcodes, only a reset gets the CPU out of it
+    jammed: bool,
+    // cycles run since power on, interrupts included; wraps around after
+    // about 300000 years of emulation
+    cycles: u64,
+    // prints every instruction executed; not part of save states
+    #[serde(skip)]
+    trace: bool,
 }
 
 impl CPU {
-    pub fn from_ram(ram: &RAM) -> CPU {
-        println!(
-            "pc at {:X}",
-            u16::from_le_bytes([ram.read(0xFFFC), ram.read(0xFFFD)])
-        );
+    // Powers the CPU on: it runs the reset sequence, which only reads the
+    // reset vector from the bus, so it starts with interrupts disabled at the
+    // address the vector points to
+    pub fn from_bus(bus: &mut dyn Bus) -> CPU {
+        let mut cpu: CPU = CPU::with_state(CpuState::default());
+        cpu.reset(bus);
+        cpu
+    }
+
+    // Reads the addresses the CPU jumps to on each interrupt, without running anything
+    pub fn vectors(bus: &mut dyn Bus) -> Vectors {
+        Vectors {
+            nmi: CPU::read_vector(bus, NMI_VECTOR),
+            reset: CPU::read_vector(bus, RESET_VECTOR),
+            irq: CPU::read_vector(bus, IRQ_VECTOR),
+        }
+    }
+
+    fn read_vector(bus: &mut dyn Bus, vector: u16) -> u16 {
+        u16::from_le_bytes([bus.read(vector), bus.read(vector + 1)])
+    }
+
+    // Same as pressing the reset button: A, X and Y are left as they were
+    pub fn reset(&mut self, bus: &mut dyn Bus) {
+        self.pc = CPU::read_vector(bus, RESET_VECTOR);
+        self.s = 0xFD;
+        self.p.set_bit(StatusFlag::InterruptDisable as u8, true);
+        self.jammed = false;
+        self.cycles = self.cycles.wrapping_add(INTERRUPT_CYCLES);
+    }
+
+    // Sets the registers directly, without reading anything from memory; p
+    // gets bit 5 set and its break bit cleared, as the register holds them
+    pub fn with_state(state: CpuState) -> CPU {
         CPU {
-            a: 0,
-            x: 0,
-            y: 0,
-            pc: u16::from_le_bytes([ram.read(0xFFFC), ram.read(0xFFFD)]),
-            s: 0,
-            p: Bitfield::new(0),
+            a: state.a,
+            x: state.x,
+            y: state.y,
+            pc: state.pc,
+            s: state.s,
+            p: CPU::status_from_bits(state.p),
+            page_crossed: false,
+            extra_cycles: 0,
+            jammed: false,
+            cycles: 0,
+            trace: false,
         }
     }
 
-    fn print_instruction(op_name: &str, mode: &AddressingMode, value: u16) {
-        match mode {
+    // Counts the cycles the CPU is halted for, e.g. during the OAM DMA
+    pub fn stall(&mut self, cycles: u64) {
+        self.cycles = self.cycles.wrapping_add(cycles);
+    }
+
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    pub fn trace(&self) -> bool {
+        self.trace
+    }
+
+    // Moves the program counter without executing anything, e.g. to 0xC000
+    // to run nestest without a PPU; a reset still goes through the reset vector
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn a(&self) -> u8 {
+        self.a
+    }
+
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    pub fn y(&self) -> u8 {
+        self.y
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn sp(&self) -> u8 {
+        self.s
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn status(&self) -> &Bitfield {
+        &self.p
+    }
+
+    pub fn flag(&self, flag: StatusFlag) -> bool {
+        self.p.get_bit(flag as u8)
+    }
+
+    // Snapshot of the registers, the counterpart of with_state
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            pc: self.pc,
+            s: self.s,
+            p: self.p.bits(),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn print_instruction(&self, bus: &mut dyn Bus, instruction: &Opcode) {
+        let op_name: &str = instruction.mnemonic;
+        let value: u16 = match instruction.mode.operand_length() {
+            0 => 0,
+            1 => bus.read(self.pc) as u16,
+            _ => u16::from_le_bytes([bus.read(self.pc), bus.read(self.pc.wrapping_add(1))]),
+        };
+        match instruction.mode {
             AddressingMode::Accumulator => println!("{} A", op_name),
             AddressingMode::Absolute => println!("{} ${:04X}", op_name, value),
             AddressingMode::AbsoluteX => println!("{} ${:04X},X", op_name, value),
             AddressingMode::AbsoluteY => println!("{} ${:04X},Y", op_name, value),
             AddressingMode::Immediate => println!("{} #${:02X}", op_name, value),
-            // AddressingMode::Implied => println!("{}", op_name),
-            AddressingMode::Indirect => println!("{} (${:02X})", op_name, value),
+            AddressingMode::Implied => println!("{}", op_name),
+            AddressingMode::Indirect => println!("{} (${:04X})", op_name, value),
             AddressingMode::IndexedIndirect => println!("{} (${:02X},X)", op_name, value),
             AddressingMode::IndirectIndexed => println!("{} (${:02X}),Y", op_name, value),
             AddressingMode::Relative | AddressingMode::ZeroPage => {
@@ -77,48 +307,315 @@ impl CPU {
         };
     }
 
-    fn read(&self, ram: &RAM, addr: u16) -> u8 {
-        ram.read(addr)
+    // Every access goes to the bus, opcode and operand fetches included: a
+    // mapper switching the bank the CPU runs from affects the next fetch
+    fn read(&self, bus: &mut dyn Bus, addr: u16) -> u8 {
+        bus.read(addr)
     }
 
-    fn read_next_byte(&mut self, ram: &RAM) -> u8 {
-        let value: u8 = self.read(ram, self.pc);
-        self.pc += 1;
+    fn read_next_byte(&mut self, bus: &mut dyn Bus) -> u8 {
+        let value: u8 = self.read(bus, self.pc);
+        // the PC wraps around from 0xFFFF to 0x0000
+        self.pc = self.pc.wrapping_add(1);
         value
     }
 
-    fn write(&mut self, ram: &mut RAM, addr: u16, data: u8) {
-        ram.write(addr, data);
+    fn write(&mut self, bus: &mut dyn Bus, addr: u16, data: u8) {
+        bus.write(addr, data);
+    }
+
+    fn read_word_number(&mut self, bus: &mut dyn Bus, addr: u16) -> u16 {
+        u16::from_le_bytes([self.read(bus, addr), self.read(bus, addr.wrapping_add(1))])
     }
 
-    fn read_word_number(&mut self, ram: &RAM, addr: u16) -> u16 {
-        u16::from_le_bytes([self.read(ram, addr), self.read(ram, addr + 1)])
+    // Pointers stored in the zero page wrap around within it
+    fn read_zero_page_word_number(&mut self, bus: &mut dyn Bus, addr: u8) -> u16 {
+        u16::from_le_bytes([
+            self.read(bus, addr as u16),
+            self.read(bus, addr.wrapping_add(1) as u16),
+        ])
     }
 
-    fn read_next_word_number(&mut self, ram: &RAM) -> u16 {
-        let res = self.read_word_number(ram, self.pc);
-        self.pc += 2;
+    // The 6502 does not carry into the high byte of the pointer of JMP ($xxFF),
+    // so its high byte is read from $xx00 instead of the next page
+    fn read_page_wrapped_word_number(&mut self, bus: &mut dyn Bus, addr: u16) -> u16 {
+        let high_addr: u16 = addr & 0xFF00 | (addr as u8).wrapping_add(1) as u16;
+        u16::from_le_bytes([self.read(bus, addr), self.read(bus, high_addr)])
+    }
+
+    fn read_next_word_number(&mut self, bus: &mut dyn Bus) -> u16 {
+        let res = self.read_word_number(bus, self.pc);
+        self.pc = self.pc.wrapping_add(2);
         res
     }
 
+    // The stack lives in page 0x0100 and grows downwards
+    // s wraps around so that the stack never leaves its page
+    fn push(&mut self, bus: &mut dyn Bus, data: u8) {
+        self.write(bus, STACK_BASE | self.s as u16, data);
+        self.s = self.s.wrapping_sub(1);
+    }
+
+    fn pull(&mut self, bus: &mut dyn Bus) -> u8 {
+        self.s = self.s.wrapping_add(1);
+        self.read(bus, STACK_BASE | self.s as u16)
+    }
+
+    // The status as pushed on the stack: bit 5 is always set, and the break
+    // flag tells PHP and BRK (set) apart from the IRQ and NMI (clear)
+    fn pushed_status(&self, break_flag: bool) -> u8 {
+        let status: u8 = self.p.bits() | UNUSED_FLAG;
+        if break_flag {
+            status | BREAK_FLAG
+        } else {
+            status & !BREAK_FLAG
+        }
+    }
+
+    // The register only holds the six real flags: the break bit of a pulled
+    // status is dropped and bit 5 always reads as set
+    fn status_from_bits(bits: u8) -> Bitfield {
+        Bitfield::from_bits(bits & !BREAK_FLAG | UNUSED_FLAG)
+    }
+
     fn is_crossing_page_boundary(addr1: u16, addr2: u16) -> bool {
         addr1 & 0xFF00 != addr2 & 0xFF00
     }
 
-    fn bcc(&mut self, ram: &RAM) -> u64 {
-        self.branch_if_comparison(ram, !self.p.get_bit(StatusFlag::Carry as u8), "BCC")
+    fn set_zero_and_negative_flags(&mut self, value: u8) {
+        self.p.set_bit(StatusFlag::Zero as u8, value == 0);
+        self.p
+            .set_bit(StatusFlag::Negative as u8, value & (1 << 7) != 0);
     }
 
-    fn bcs(&mut self, ram: &RAM) -> u64 {
-        self.branch_if_comparison(ram, self.p.get_bit(StatusFlag::Carry as u8), "BCS")
+    // Reads the value at the operand address, writes back the result of op and returns it
+    // In Accumulator mode, the result goes back into A without touching memory
+    // Like the 6502, the unmodified value is written back before the result,
+    // which matters for memory-mapped registers
+    fn read_modify_write(
+        &mut self,
+        bus: &mut dyn Bus,
+        mode: &AddressingMode,
+        op: fn(&mut CPU, u8) -> u8,
+    ) -> u8 {
+        if let AddressingMode::Accumulator = mode {
+            let result: u8 = op(self, self.a);
+            self.a = result;
+            return result;
+        }
+        let addr: u16 = self.get_address(bus, mode);
+        self.dummy_read_indexed(bus, mode, addr, true);
+        let value: u8 = self.read(bus, addr);
+        let result: u8 = op(self, value);
+        self.write(bus, addr, value);
+        self.write(bus, addr, result);
+        result
     }
 
-    fn beq(&mut self, ram: &RAM) -> u64 {
-        self.branch_if_comparison(ram, self.p.get_bit(StatusFlag::Zero as u8), "BEQ")
+    // Indexed addressing adds the index to the low byte of the address first
+    // and reads the result while the carry is added to the high byte: reads
+    // only do this dummy read when the index crossed a page, since they can
+    // use the value right away otherwise, while stores and read-modify-write
+    // instructions always do it
+    fn dummy_read_indexed(
+        &mut self,
+        bus: &mut dyn Bus,
+        mode: &AddressingMode,
+        addr: u16,
+        is_write: bool,
+    ) {
+        if let AddressingMode::AbsoluteX
+        | AddressingMode::AbsoluteY
+        | AddressingMode::IndirectIndexed = mode
+        {
+            if !is_write && !self.page_crossed {
+                return;
+            }
+            let unfixed_addr: u16 = if self.page_crossed {
+                addr.wrapping_sub(0x100)
+            } else {
+                addr
+            };
+            self.read(bus, unfixed_addr);
+        }
     }
 
-    fn bit(&mut self, ram: &RAM, mode: &AddressingMode) {
-        let value = self.get_value(ram, mode).0 as u8;
+    // The 2A03 has no decimal mode, ADC and SBC always work in binary on the NES
+    fn add_with_carry(&mut self, value: u8) {
+        #[cfg(feature = "decimal")]
+        if self.p.get_bit(StatusFlag::DecimalMode as u8) {
+            self.decimal_add(value);
+            return;
+        }
+        self.binary_add(value);
+    }
+
+    fn binary_add(&mut self, value: u8) {
+        let sum: u16 =
+            self.a as u16 + value as u16 + self.p.get_bit(StatusFlag::Carry as u8) as u16;
+        let result: u8 = sum as u8;
+        self.p.set_bit(StatusFlag::Carry as u8, sum > 0xFF);
+        // overflow when both operands have the same sign and the result does not
+        self.p.set_bit(
+            StatusFlag::Overflow as u8,
+            (self.a ^ result) & (value ^ result) & (1 << 7) != 0,
+        );
+        self.set_register(Register::A, result);
+    }
+
+    fn subtract_with_borrow(&mut self, value: u8) {
+        #[cfg(feature = "decimal")]
+        if self.p.get_bit(StatusFlag::DecimalMode as u8) {
+            self.decimal_subtract(value);
+            return;
+        }
+        self.binary_add(!value);
+    }
+
+    // BCD addition as done by the NMOS 6502: Z comes from the binary sum, N
+    // and V from the sum before the high digit is adjusted
+    #[cfg(feature = "decimal")]
+    fn decimal_add(&mut self, value: u8) {
+        let a: u8 = self.a;
+        let carry: u16 = self.p.get_bit(StatusFlag::Carry as u8) as u16;
+        self.binary_add(value);
+        let mut low: u16 = (a & 0x0F) as u16 + (value & 0x0F) as u16 + carry;
+        if low >= 0x0A {
+            low = ((low + 0x06) & 0x0F) + 0x10;
+        }
+        let mut sum: u16 = (a & 0xF0) as u16 + (value & 0xF0) as u16 + low;
+        let intermediate: u8 = sum as u8;
+        self.p
+            .set_bit(StatusFlag::Negative as u8, intermediate & (1 << 7) != 0);
+        self.p.set_bit(
+            StatusFlag::Overflow as u8,
+            (a ^ intermediate) & (value ^ intermediate) & (1 << 7) != 0,
+        );
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+        self.p.set_bit(StatusFlag::Carry as u8, sum > 0xFF);
+        self.a = sum as u8;
+    }
+
+    // BCD subtraction as done by the NMOS 6502: the flags are the binary ones
+    #[cfg(feature = "decimal")]
+    fn decimal_subtract(&mut self, value: u8) {
+        let a: u8 = self.a;
+        let borrow: i16 = 1 - self.p.get_bit(StatusFlag::Carry as u8) as i16;
+        self.binary_add(!value);
+        let mut low: i16 = (a & 0x0F) as i16 - (value & 0x0F) as i16 - borrow;
+        if low < 0 {
+            low = ((low - 0x06) & 0x0F) - 0x10;
+        }
+        let mut difference: i16 = (a & 0xF0) as i16 - (value & 0xF0) as i16 + low;
+        if difference < 0 {
+            difference -= 0x60;
+        }
+        self.a = difference as u8;
+    }
+
+    fn compare(&mut self, register: u8, value: u8) {
+        self.p.set_bit(StatusFlag::Carry as u8, register >= value);
+        self.set_zero_and_negative_flags(register.wrapping_sub(value));
+    }
+
+    fn shift_left(&mut self, value: u8) -> u8 {
+        self.p
+            .set_bit(StatusFlag::Carry as u8, value & (1 << 7) != 0);
+        let result: u8 = value << 1;
+        self.set_zero_and_negative_flags(result);
+        result
+    }
+
+    fn shift_right(&mut self, value: u8) -> u8 {
+        self.p.set_bit(StatusFlag::Carry as u8, value & 1 != 0);
+        let result: u8 = value >> 1;
+        self.set_zero_and_negative_flags(result);
+        result
+    }
+
+    fn rotate_left(&mut self, value: u8) -> u8 {
+        let carry: u8 = self.p.get_bit(StatusFlag::Carry as u8) as u8;
+        self.p
+            .set_bit(StatusFlag::Carry as u8, value & (1 << 7) != 0);
+        let result: u8 = value << 1 | carry;
+        self.set_zero_and_negative_flags(result);
+        result
+    }
+
+    fn rotate_right(&mut self, value: u8) -> u8 {
+        let carry: u8 = self.p.get_bit(StatusFlag::Carry as u8) as u8;
+        self.p.set_bit(StatusFlag::Carry as u8, value & 1 != 0);
+        let result: u8 = carry << 7 | value >> 1;
+        self.set_zero_and_negative_flags(result);
+        result
+    }
+
+    fn adc(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.get_value(bus, mode);
+        self.add_with_carry(value);
+    }
+
+    // Unofficial: AND then LSR on the accumulator
+    fn alr(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.get_value(bus, mode);
+        self.a = self.shift_right(self.a & value);
+    }
+
+    // Unofficial: AND, then the Negative flag is copied into Carry
+    fn anc(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.get_value(bus, mode);
+        self.set_register(Register::A, self.a & value);
+        self.p
+            .set_bit(StatusFlag::Carry as u8, self.a & (1 << 7) != 0);
+    }
+
+    fn and(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.get_value(bus, mode);
+        self.set_register(Register::A, self.a & value);
+    }
+
+    // Unofficial: AND then ROR on the accumulator, with Carry taken from bit 6
+    // of the result and Overflow from bit 6 XOR bit 5
+    fn arr(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.get_value(bus, mode);
+        let result: u8 = self.rotate_right(self.a & value);
+        self.a = result;
+        self.p
+            .set_bit(StatusFlag::Carry as u8, result & (1 << 6) != 0);
+        self.p.set_bit(
+            StatusFlag::Overflow as u8,
+            ((result >> 6) ^ (result >> 5)) & 1 != 0,
+        );
+    }
+
+    fn asl(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.read_modify_write(bus, mode, CPU::shift_left);
+    }
+
+    // Unofficial: X = (A & X) - value, setting the flags as CMP does
+    fn axs(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.get_value(bus, mode);
+        let operand: u8 = self.a & self.x;
+        self.compare(operand, value);
+        self.x = operand.wrapping_sub(value);
+    }
+
+    fn bcc(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.branch_if_comparison(bus, mode, !self.p.get_bit(StatusFlag::Carry as u8))
+    }
+
+    fn bcs(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.branch_if_comparison(bus, mode, self.p.get_bit(StatusFlag::Carry as u8))
+    }
+
+    fn beq(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.branch_if_comparison(bus, mode, self.p.get_bit(StatusFlag::Zero as u8))
+    }
+
+    fn bit(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.get_value(bus, mode);
         let result: u8 = self.a & value;
         self.p.set_bit(StatusFlag::Zero as u8, result == 0);
         self.p
@@ -127,393 +624,806 @@ impl CPU {
             .set_bit(StatusFlag::Negative as u8, value & (1 << 7) != 0);
     }
 
-    fn bmi(&mut self, ram: &RAM) -> u64 {
-        self.branch_if_comparison(ram, self.p.get_bit(StatusFlag::Negative as u8), "BMI")
+    fn bmi(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.branch_if_comparison(bus, mode, self.p.get_bit(StatusFlag::Negative as u8))
     }
 
-    fn bne(&mut self, ram: &RAM) -> u64 {
-        self.branch_if_comparison(ram, !self.p.get_bit(StatusFlag::Zero as u8), "BNE")
+    fn bne(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.branch_if_comparison(bus, mode, !self.p.get_bit(StatusFlag::Zero as u8))
     }
 
-    fn bpl(&mut self, ram: &RAM) -> u64 {
-        self.branch_if_comparison(ram, !self.p.get_bit(StatusFlag::Negative as u8), "BPL")
+    fn bpl(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.branch_if_comparison(bus, mode, !self.p.get_bit(StatusFlag::Negative as u8))
     }
 
-    fn branch_if_comparison(&mut self, ram: &RAM, condition: bool, op_name: &str) -> u64 {
-        let mut cycles: u64 = 2;
-        let (new_location, page_boundary_crossed) = self.get_value(ram, &AddressingMode::Relative);
-        println!(
-            "{} ${:02X}",
-            op_name,
-            (new_location as i32 - self.pc as i32) as u8
-        );
+    fn branch_if_comparison(&mut self, bus: &mut dyn Bus, mode: &AddressingMode, condition: bool) {
+        let new_location: u16 = self.get_address(bus, mode);
         if condition {
             self.pc = new_location;
-            cycles += if page_boundary_crossed { 2 } else { 1 };
+            self.extra_cycles += if self.page_crossed { 2 } else { 1 };
         }
-        cycles
     }
 
-    fn dec(&mut self, ram: &mut RAM, mode: &AddressingMode) {
-        let addr: u16 = self.get_value(ram, mode).0;
-        Self::print_instruction("DEC", mode, addr);
-        let value: u8 = self.read(ram, addr).wrapping_sub(1);
-        self.write(ram, addr, value);
-        self.p.set_bit(StatusFlag::Zero as u8, value == 0);
-        self.p
-            .set_bit(StatusFlag::Negative as u8, value & (1 << 7) != 0);
+    // Software interrupt through the IRQ vector; the byte after the opcode is
+    // skipped, so RTI returns two bytes after the BRK
+    fn brk(&mut self, bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.read_next_byte(bus);
+        let status: u8 = self.pushed_status(true);
+        self.enter_interrupt(bus, IRQ_VECTOR, status);
     }
 
-    fn decrement_register(name: &str, p: &mut Bitfield, reg: &mut u8) -> u64 {
-        println!("{}", name);
-        *reg = reg.wrapping_sub(1);
-        p.set_bit(StatusFlag::Zero as u8, *reg == 0);
-        p
-            .set_bit(StatusFlag::Negative as u8, *reg & (1 << 7) != 0);
-        2
+    fn bvc(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.branch_if_comparison(bus, mode, !self.p.get_bit(StatusFlag::Overflow as u8))
     }
 
-    fn jmp(&mut self, ram: &RAM, mode: &AddressingMode) {
-        let (addr, _) = self.get_value(ram, mode);
-        Self::print_instruction("JMP", mode, addr);
-        self.pc = addr;
+    fn bvs(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.branch_if_comparison(bus, mode, self.p.get_bit(StatusFlag::Overflow as u8))
     }
 
-    fn inc(&mut self, ram: &mut RAM, mode: &AddressingMode) {
-        let addr: u16 = self.get_value(ram, mode).0;
-        Self::print_instruction("INC", mode, addr);
-        let value: u8 = self.read(ram, addr).wrapping_add(1);
-        self.write(ram, addr, value);
-        self.p.set_bit(StatusFlag::Zero as u8, value == 0);
-        self.p
-            .set_bit(StatusFlag::Negative as u8, value & (1 << 7) != 0);
+    fn clc(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.p.set_bit(StatusFlag::Carry as u8, false);
     }
 
-    fn increment_register(name: &str, p: &mut Bitfield, reg: &mut u8) -> u64 {
-        println!("{}", name);
-        *reg = reg.wrapping_add(1);
-        p.set_bit(StatusFlag::Zero as u8, *reg == 0);
-        p
-            .set_bit(StatusFlag::Negative as u8, *reg & (1 << 7) != 0);
-        2
+    fn cld(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.p.set_bit(StatusFlag::DecimalMode as u8, false);
     }
 
-    fn lda(&mut self, ram: &mut RAM, mode: &AddressingMode) -> u64 {
-        let (value, cycles) = self.load_into_register(ram, mode, Register::A);
-        Self::print_instruction("LDA", mode, value as u16);
-        cycles
+    fn cli(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.p.set_bit(StatusFlag::InterruptDisable as u8, false);
     }
 
-    fn ldx(&mut self, ram: &mut RAM, mode: &AddressingMode) -> u64 {
-        let (value, cycles) = self.load_into_register(ram, mode, Register::X);
-        Self::print_instruction("LDX", mode, value as u16);
-        cycles
+    fn clv(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.p.set_bit(StatusFlag::Overflow as u8, false);
     }
 
-    fn ldy(&mut self, ram: &mut RAM, mode: &AddressingMode) -> u64 {
-        let (value, cycles) = self.load_into_register(ram, mode, Register::Y);
-        Self::print_instruction("LDY", mode, value as u16);
-        cycles
+    fn cmp(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.get_value(bus, mode);
+        self.compare(self.a, value);
     }
 
-    fn load_into_register(
-        &mut self,
-        ram: &mut RAM,
-        mode: &AddressingMode,
-        register: Register,
-    ) -> (u8, u64) {
-        let result = self.get_value(ram, mode);
-        let value = result.0 as u8;
-        let cycles = match mode {
-            AddressingMode::Immediate => 2,
-            AddressingMode::ZeroPage => 3,
-            AddressingMode::ZeroPageX
-            | AddressingMode::ZeroPageY
-            | AddressingMode::Absolute
-            | AddressingMode::AbsoluteX
-            | AddressingMode::AbsoluteY => 4 + result.1 as u64,
-            AddressingMode::IndexedIndirect => 6,
-            AddressingMode::IndirectIndexed => 5 + result.1 as u64,
-            _ => 0,
-        };
-        self.p.set_bit(StatusFlag::Zero as u8, value == 0);
-        self.p
-            .set_bit(StatusFlag::Negative as u8, value & (1 << 7) != 0);
+    fn cpx(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.get_value(bus, mode);
+        self.compare(self.x, value);
+    }
+
+    fn cpy(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.get_value(bus, mode);
+        self.compare(self.y, value);
+    }
+
+    // Unofficial: DEC then CMP
+    fn dcp(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.read_modify_write(bus, mode, |_, value| value.wrapping_sub(1));
+        self.compare(self.a, value);
+    }
+
+    fn dec(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.read_modify_write(bus, mode, |_, value| value.wrapping_sub(1));
+        self.set_zero_and_negative_flags(value);
+    }
+
+    fn decrement_register(&mut self, register: Register) {
+        let value: u8 = match register {
+            Register::A => self.a,
+            Register::X => self.x,
+            Register::Y => self.y,
+        }
+        .wrapping_sub(1);
+        self.set_register(register, value);
+    }
+
+    fn dex(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.decrement_register(Register::X);
+    }
+
+    fn dey(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.decrement_register(Register::Y);
+    }
+
+    fn eor(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.get_value(bus, mode);
+        self.set_register(Register::A, self.a ^ value);
+    }
+
+    // Unofficial: halts the CPU, which keeps fetching the same opcode
+    fn jam(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.pc = self.pc.wrapping_sub(1);
+        self.jammed = true;
+    }
+
+    fn jmp(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.pc = self.get_address(bus, mode);
+    }
+
+    // JSR fetches the low byte of the target, idles on a stack read, pushes the
+    // return address (pointing at its last operand byte) and only then fetches the high byte
+    fn jsr(&mut self, bus: &mut dyn Bus, _mode: &AddressingMode) {
+        let low: u8 = self.read_next_byte(bus);
+        self.read(bus, STACK_BASE | self.s as u16);
+        let [pc_low, pc_high] = self.pc.to_le_bytes();
+        self.push(bus, pc_high);
+        self.push(bus, pc_low);
+        let high: u8 = self.read(bus, self.pc);
+        self.pc = u16::from_le_bytes([low, high]);
+    }
+
+    fn inc(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.read_modify_write(bus, mode, |_, value| value.wrapping_add(1));
+        self.set_zero_and_negative_flags(value);
+    }
+
+    fn increment_register(&mut self, register: Register) {
+        let value: u8 = match register {
+            Register::A => self.a,
+            Register::X => self.x,
+            Register::Y => self.y,
+        }
+        .wrapping_add(1);
+        self.set_register(register, value);
+    }
+
+    fn inx(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.increment_register(Register::X);
+    }
+
+    fn iny(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.increment_register(Register::Y);
+    }
+
+    // Unofficial: INC then SBC
+    fn isc(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.read_modify_write(bus, mode, |_, value| value.wrapping_add(1));
+        self.subtract_with_borrow(value);
+    }
+
+    // Unofficial: LDA and LDX at once
+    fn lax(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.get_value(bus, mode);
+        self.set_register(Register::A, value);
+        self.x = value;
+    }
+
+    fn lda(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.load_into_register(bus, mode, Register::A);
+    }
+
+    fn ldx(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.load_into_register(bus, mode, Register::X);
+    }
+
+    fn ldy(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.load_into_register(bus, mode, Register::Y);
+    }
+
+    fn load_into_register(&mut self, bus: &mut dyn Bus, mode: &AddressingMode, register: Register) {
+        let value: u8 = self.get_value(bus, mode);
+        self.set_register(register, value);
+    }
+
+    fn lsr(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.read_modify_write(bus, mode, CPU::shift_right);
+    }
+
+    // The unofficial NOPs with an operand still read it
+    fn nop(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        if !matches!(mode, AddressingMode::Implied) {
+            self.get_value(bus, mode);
+        }
+    }
+
+    fn ora(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.get_value(bus, mode);
+        self.set_register(Register::A, self.a | value);
+    }
+
+    fn pha(&mut self, bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.push(bus, self.a);
+    }
+
+    // PHP pushes the status with the break flag set, unlike interrupts
+    fn php(&mut self, bus: &mut dyn Bus, _mode: &AddressingMode) {
+        let status: u8 = self.pushed_status(true);
+        self.push(bus, status);
+    }
+
+    fn pla(&mut self, bus: &mut dyn Bus, _mode: &AddressingMode) {
+        let value: u8 = self.pull(bus);
+        self.set_register(Register::A, value);
+    }
+
+    // The break and unused bits pulled are ignored
+    fn plp(&mut self, bus: &mut dyn Bus, _mode: &AddressingMode) {
+        let value: u8 = self.pull(bus);
+        self.p = CPU::status_from_bits(value);
+    }
+
+    // Unofficial: ROL then AND
+    fn rla(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.read_modify_write(bus, mode, CPU::rotate_left);
+        self.set_register(Register::A, self.a & value);
+    }
+
+    fn rol(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.read_modify_write(bus, mode, CPU::rotate_left);
+    }
+
+    fn ror(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        self.read_modify_write(bus, mode, CPU::rotate_right);
+    }
+
+    // Unofficial: ROR then ADC
+    fn rra(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.read_modify_write(bus, mode, CPU::rotate_right);
+        self.add_with_carry(value);
+    }
+
+    // The break and unused bits pulled are ignored, as with PLP
+    fn rti(&mut self, bus: &mut dyn Bus, _mode: &AddressingMode) {
+        let status: u8 = self.pull(bus);
+        self.p = CPU::status_from_bits(status);
+        let pc_low: u8 = self.pull(bus);
+        let pc_high: u8 = self.pull(bus);
+        self.pc = u16::from_le_bytes([pc_low, pc_high]);
+    }
+
+    // Pulls the address pushed by JSR and resumes right after its last operand byte
+    fn rts(&mut self, bus: &mut dyn Bus, _mode: &AddressingMode) {
+        let pc_low: u8 = self.pull(bus);
+        let pc_high: u8 = self.pull(bus);
+        self.pc = u16::from_le_bytes([pc_low, pc_high]).wrapping_add(1);
+    }
+
+    // Unofficial: stores A & X without affecting the flags
+    fn sax(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let addr: u16 = self.get_address(bus, mode);
+        self.write(bus, addr, self.a & self.x);
+    }
+
+    fn sbc(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.get_value(bus, mode);
+        self.subtract_with_borrow(value);
+    }
+
+    fn sec(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.p.set_bit(StatusFlag::Carry as u8, true);
+    }
+
+    fn sed(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.p.set_bit(StatusFlag::DecimalMode as u8, true);
+    }
+
+    fn sei(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.p.set_bit(StatusFlag::InterruptDisable as u8, true);
+    }
+
+    // Sets the register and updates the Zero and Negative flags accordingly
+    fn set_register(&mut self, register: Register, value: u8) {
+        self.set_zero_and_negative_flags(value);
         match register {
             Register::A => self.a = value,
             Register::X => self.x = value,
             Register::Y => self.y = value,
         }
-        (value, cycles)
     }
 
-    fn sta(&mut self, ram: &mut RAM, mode: &AddressingMode) {
-        let addr: u16 = self.get_value(ram, mode).0;
-        Self::print_instruction("STA", mode, addr);
-        self.write(ram, addr, self.a);
+    // Unofficial: ASL then ORA
+    fn slo(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.read_modify_write(bus, mode, CPU::shift_left);
+        self.set_register(Register::A, self.a | value);
+    }
+
+    // Unofficial: LSR then EOR
+    fn sre(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let value: u8 = self.read_modify_write(bus, mode, CPU::shift_right);
+        self.set_register(Register::A, self.a ^ value);
+    }
+
+    fn sta(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let addr: u16 = self.get_address(bus, mode);
+        self.dummy_read_indexed(bus, mode, addr, true);
+        self.write(bus, addr, self.a);
     }
 
-    fn stx(&mut self, ram: &mut RAM, mode: &AddressingMode) {
-        let addr: u16 = self.get_value(ram, mode).0;
-        Self::print_instruction("STX", mode, addr);
-        self.write(ram, addr, self.x);
+    fn stx(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let addr: u16 = self.get_address(bus, mode);
+        self.write(bus, addr, self.x);
     }
 
-    fn sty(&mut self, ram: &mut RAM, mode: &AddressingMode) {
-        let addr: u16 = self.get_value(ram, mode).0;
-        Self::print_instruction("STY", mode, addr);
-        self.write(ram, addr, self.y);
+    fn sty(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) {
+        let addr: u16 = self.get_address(bus, mode);
+        self.write(bus, addr, self.y);
     }
 
-    fn transfer_accumulator_to(name: &str, p: &mut Bitfield, src: u8, dest: &mut u8) -> u64 {
-        println!("{}", name);
-        *dest = src;
-        p.set_bit(StatusFlag::Zero as u8, src == 0);
-        p.set_bit(StatusFlag::Negative as u8, src & (1 << 7) != 0);
-        2
+    fn tax(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.set_register(Register::X, self.a);
     }
 
-    fn get_value(&mut self, ram: &RAM, mode: &AddressingMode) -> (u16, bool) {
+    fn tay(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.set_register(Register::Y, self.a);
+    }
+
+    fn tsx(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.set_register(Register::X, self.s);
+    }
+
+    fn txa(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.set_register(Register::A, self.x);
+    }
+
+    // TXS is the only transfer that leaves the flags untouched
+    fn txs(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.s = self.x;
+    }
+
+    fn tya(&mut self, _bus: &mut dyn Bus, _mode: &AddressingMode) {
+        self.set_register(Register::A, self.y);
+    }
+
+    // Resolves the effective address of the operand, consuming its bytes
+    // For Relative mode, this is the branch target
+    fn get_address(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) -> u16 {
         match mode {
-            AddressingMode::Accumulator => (self.a as u16, false),
-            AddressingMode::Absolute => {
-                let addr: u16 = self.read_next_word_number(ram);
-                (self.read(ram, addr) as u16, false)
-            }
+            AddressingMode::Absolute => self.read_next_word_number(bus),
             AddressingMode::AbsoluteX => {
-                let addr: u16 = self.read_next_word_number(ram)
-                    + self.x as u16
-                    + self.p.get_bit(StatusFlag::Carry as u8) as u16;
-                (
-                    self.read(ram, addr) as u16,
-                    CPU::is_crossing_page_boundary(addr, addr - self.x as u16),
-                )
+                let base: u16 = self.read_next_word_number(bus);
+                let addr: u16 = base.wrapping_add(self.x as u16);
+                self.page_crossed = CPU::is_crossing_page_boundary(base, addr);
+                addr
             }
             AddressingMode::AbsoluteY => {
-                let addr: u16 = self.read_next_word_number(ram)
-                    + self.y as u16
-                    + self.p.get_bit(StatusFlag::Carry as u8) as u16;
-                (
-                    self.read(ram, addr) as u16,
-                    CPU::is_crossing_page_boundary(addr, addr - self.y as u16),
-                )
+                let base: u16 = self.read_next_word_number(bus);
+                let addr: u16 = base.wrapping_add(self.y as u16);
+                self.page_crossed = CPU::is_crossing_page_boundary(base, addr);
+                addr
             }
             AddressingMode::Immediate => {
-                let value: u8 = self.read_next_byte(ram);
-                (value as u16, false)
+                let addr: u16 = self.pc;
+                self.pc = self.pc.wrapping_add(1);
+                addr
             }
-            // AddressingMode::Implied => (0, false),
             AddressingMode::Indirect => {
-                let addr: u16 = self.read_next_word_number(ram);
-                (self.read_word_number(ram, addr), false)
+                let addr: u16 = self.read_next_word_number(bus);
+                self.read_page_wrapped_word_number(bus, addr)
             }
             AddressingMode::IndexedIndirect => {
-                let addr: u8 = self.read_next_byte(ram);
-                (
-                    self.read_word_number(ram, (addr as u16 + self.x as u16) & 0xFF),
-                    false,
-                )
+                let addr: u8 = self.read_next_byte(bus);
+                self.read_zero_page_word_number(bus, addr.wrapping_add(self.x))
             }
             AddressingMode::IndirectIndexed => {
-                let addr: u8 = self.read_next_byte(ram);
-                let indirect_addr: u16 = self.read_word_number(ram, addr as u16);
-                let new_location: u16 = indirect_addr + self.y as u16;
-                (
-                    self.read(ram, new_location) as u16,
-                    CPU::is_crossing_page_boundary(indirect_addr, new_location),
-                )
+                let addr: u8 = self.read_next_byte(bus);
+                let indirect_addr: u16 = self.read_zero_page_word_number(bus, addr);
+                let new_location: u16 = indirect_addr.wrapping_add(self.y as u16);
+                self.page_crossed = CPU::is_crossing_page_boundary(indirect_addr, new_location);
+                new_location
             }
             AddressingMode::Relative => {
-                let offset: i8 = self.read_next_byte(ram) as i8;
-                let pc: i32 = self.pc as i32;
-                let new_location: u16 = (pc + offset as i32) as u16;
-                (
-                    new_location,
-                    CPU::is_crossing_page_boundary(self.pc, new_location),
-                )
+                let offset: i8 = self.read_next_byte(bus) as i8;
+                let new_location: u16 = self.pc.wrapping_add(offset as u16);
+                self.page_crossed = CPU::is_crossing_page_boundary(self.pc, new_location);
+                new_location
             }
-            AddressingMode::ZeroPage => {
-                let addr: u8 = self.read_next_byte(ram);
-                (self.read(ram, addr as u16) as u16, false)
-            }
-            AddressingMode::ZeroPageX => {
-                let addr: u8 = self.read_next_byte(ram);
-                (self.read(ram, (addr + self.x) as u16) as u16, false)
-            }
-            AddressingMode::ZeroPageY => {
-                let addr: u8 = self.read_next_byte(ram);
-                (self.read(ram, (addr + self.y) as u16) as u16, false)
+            AddressingMode::ZeroPage => self.read_next_byte(bus) as u16,
+            AddressingMode::ZeroPageX => self.read_next_byte(bus).wrapping_add(self.x) as u16,
+            AddressingMode::ZeroPageY => self.read_next_byte(bus).wrapping_add(self.y) as u16,
+            AddressingMode::Accumulator | AddressingMode::Implied => {
+                unreachable!("{:?} mode has no operand address", mode)
             }
         }
     }
 
-    fn execute_next_instruction(&mut self, ram: &mut RAM) -> u64 {
-        let opcode: u8 = self.read_next_byte(ram);
-        match opcode {
-            0x00 => {
-                println!("BRK");
-                std::process::exit(0);
-            }
-            0x10 => self.bpl(ram),
-            0x24 => {
-                self.bit(ram, &AddressingMode::ZeroPage);
-                3
-            }
-            0x2C => {
-                self.bit(ram, &AddressingMode::Absolute);
-                4
-            }
-            0x4C => {
-                self.jmp(ram, &AddressingMode::Absolute);
-                3
-            }
-            0x30 => self.bmi(ram),
-            0x6C => {
-                self.jmp(ram, &AddressingMode::Indirect);
-                5
-            }
-            0x78 => {
-                println!("SEI");
-                self.p.set_bit(StatusFlag::InterruptDisable as u8, true);
-                2
-            }
-            0x81 => {
-                self.sta(ram, &AddressingMode::IndexedIndirect);
-                6
-            }
-            0x84 => {
-                self.sty(ram, &AddressingMode::ZeroPage);
-                3
-            }
-            0x85 => {
-                self.sta(ram, &AddressingMode::ZeroPage);
-                3
-            }
-            0x86 => {
-                self.stx(ram, &AddressingMode::ZeroPage);
-                3
-            }
-            0x88 => Self::decrement_register("DEY", &mut self.p, &mut self.y),
-            0x8A => Self::transfer_accumulator_to("TXA", &mut self.p, self.x, &mut self.a),
-            0x8C => {
-                self.sty(ram, &AddressingMode::Absolute);
-                4
-            }
-            0x8D => {
-                self.sta(ram, &AddressingMode::Absolute);
-                4
-            }
-            0x8E => {
-                self.stx(ram, &AddressingMode::Absolute);
-                4
-            }
-            0x90 => self.bcc(ram),
-            0x94 => {
-                self.sty(ram, &AddressingMode::ZeroPageX);
-                4
-            }
-            0x91 => {
-                self.sta(ram, &AddressingMode::IndirectIndexed);
-                6
-            }
-            0x95 => {
-                self.sta(ram, &AddressingMode::ZeroPageX);
-                4
-            }
-            0x96 => {
-                self.stx(ram, &AddressingMode::ZeroPageY);
-                4
-            }
-            0x98 => Self::transfer_accumulator_to("TYA", &mut self.p, self.y, &mut self.a),
-            0x99 => {
-                self.sta(ram, &AddressingMode::AbsoluteY);
-                5
-            }
-            0x9A => Self::transfer_accumulator_to("TXS", &mut self.p, self.x, &mut self.s),
-            0x9D => {
-                self.sta(ram, &AddressingMode::AbsoluteX);
-                5
-            }
-            0xA0 => self.ldy(ram, &AddressingMode::Immediate),
-            0xA1 => self.lda(ram, &AddressingMode::IndexedIndirect),
-            0xA2 => self.ldx(ram, &AddressingMode::Immediate),
-            0xA4 => self.ldy(ram, &AddressingMode::ZeroPage),
-            0xA5 => self.lda(ram, &AddressingMode::ZeroPage),
-            0xA6 => self.ldx(ram, &AddressingMode::ZeroPage),
-            0xA8 => Self::transfer_accumulator_to("TAY", &mut self.p, self.a, &mut self.y),
-            0xA9 => self.lda(ram, &AddressingMode::Immediate),
-            0xAA => Self::transfer_accumulator_to("TAX", &mut self.p, self.a, &mut self.x),
-            0xAC => self.ldy(ram, &AddressingMode::Absolute),
-            0xAD => self.lda(ram, &AddressingMode::Absolute),
-            0xAE => self.ldx(ram, &AddressingMode::Absolute),
-            0xB0 => self.bcs(ram),
-            0xB1 => self.lda(ram, &AddressingMode::IndirectIndexed),
-            0xB4 => self.ldy(ram, &AddressingMode::ZeroPageX),
-            0xB5 => self.lda(ram, &AddressingMode::ZeroPageX),
-            0xB9 => self.lda(ram, &AddressingMode::AbsoluteY),
-            0xBA => Self::transfer_accumulator_to("TSX", &mut self.p, self.s, &mut self.x),
-            0xBC => self.ldy(ram, &AddressingMode::AbsoluteX),
-            0xBD => self.lda(ram, &AddressingMode::AbsoluteX),
-            0xBE => self.ldx(ram, &AddressingMode::AbsoluteY),
-            0xB6 => self.ldx(ram, &AddressingMode::ZeroPageY),
-            0xC6 => {
-                self.dec(ram, &AddressingMode::ZeroPage);
-                5
-            }
-            0xC8 => Self::increment_register("INY", &mut self.p, &mut self.y),
-            0xCA => Self::decrement_register("DEX", &mut self.p, &mut self.x),
-            0xCE => {
-                self.dec(ram, &AddressingMode::Absolute);
-                6
-            }
-            0xD0 => self.bne(ram),
-            0xD6 => {
-                self.dec(ram, &AddressingMode::ZeroPageX);
-                6
-            }
-            0xD8 => {
-                println!("CLD");
-                self.p.set_bit(StatusFlag::DecimalMode as u8, false);
-                2
-            }
-            0xDE => {
-                self.dec(ram, &AddressingMode::AbsoluteX);
-                7
-            }
-            0xE6 => {
-                self.inc(ram, &AddressingMode::ZeroPage);
-                5
-            }
-            0xE8 => Self::increment_register("INX", &mut self.p, &mut self.x),
-            0xEA => {
-                println!("NOP");
-                2
-            }
-            0xEE => {
-                self.inc(ram, &AddressingMode::Absolute);
-                6
-            }
-            0xF0 => self.beq(ram),
-            0xF6 => {
-                self.inc(ram, &AddressingMode::ZeroPageX);
-                6
-            }
-            0xFE => {
-                self.inc(ram, &AddressingMode::AbsoluteX);
-                7
-            }
+    // Fetches the operand value, reading through its effective address if needed
+    fn get_value(&mut self, bus: &mut dyn Bus, mode: &AddressingMode) -> u8 {
+        match mode {
+            AddressingMode::Accumulator => self.a,
             _ => {
-                eprintln!("Unknown opcode: {:#X}", opcode);
-                std::process::exit(1);
+                let addr: u16 = self.get_address(bus, mode);
+                self.dummy_read_indexed(bus, mode, addr, false);
+                self.read(bus, addr)
             }
         }
     }
 
-    pub fn execute_instructions(&mut self, ram: &mut RAM, n_instructions: u64) -> u64 {
+    // Services a maskable interrupt request unless InterruptDisable is set, and
+    // returns the number of cycles it took
+    pub fn irq(&mut self, bus: &mut dyn Bus) -> u64 {
+        if self.p.get_bit(StatusFlag::InterruptDisable as u8) {
+            return 0;
+        }
+        self.interrupt(bus, IRQ_VECTOR);
+        INTERRUPT_CYCLES
+    }
+
+    // Services a non-maskable interrupt, raised by the PPU at the start of
+    // the vertical blank, and returns the number of cycles it took
+    pub fn nmi(&mut self, bus: &mut dyn Bus) -> u64 {
+        self.interrupt(bus, NMI_VECTOR);
+        INTERRUPT_CYCLES
+    }
+
+    // Pushes the return address and the given status, then jumps through the vector
+    fn enter_interrupt(&mut self, bus: &mut dyn Bus, vector: u16, status: u8) {
+        let [pc_low, pc_high] = self.pc.to_le_bytes();
+        self.push(bus, pc_high);
+        self.push(bus, pc_low);
+        self.push(bus, status);
+        self.p.set_bit(StatusFlag::InterruptDisable as u8, true);
+        self.pc = CPU::read_vector(bus, vector);
+    }
+
+    // Hardware interrupts push the status with the break flag clear
+    fn interrupt(&mut self, bus: &mut dyn Bus, vector: u16) {
+        let status: u8 = self.pushed_status(false);
+        self.enter_interrupt(bus, vector, status);
+        self.cycles = self.cycles.wrapping_add(INTERRUPT_CYCLES);
+    }
+
+    // Executes the next instruction and returns the number of cycles it took
+    pub fn step(&mut self, bus: &mut dyn Bus) -> Result<u64, CpuError> {
+        if self.jammed {
+            return Err(CpuError::Jammed(self.pc));
+        }
+        let addr: u16 = self.pc;
+        let opcode: u8 = self.read_next_byte(bus);
+        let Some(instruction) = OPCODES[opcode as usize] else {
+            return Err(CpuError::UnknownOpcode { opcode, addr });
+        };
+        #[cfg(feature = "std")]
+        if self.trace {
+            self.print_instruction(bus, &instruction);
+        }
+        self.page_crossed = false;
+        self.extra_cycles = 0;
+        (instruction.handler)(self, bus, &instruction.mode);
+        if self.jammed {
+            return Err(CpuError::Jammed(self.pc));
+        }
+        let mut cycles: u64 = instruction.cycles + self.extra_cycles;
+        if instruction.page_cross_penalty && self.page_crossed {
+            cycles += 1;
+        }
+        self.cycles = self.cycles.wrapping_add(cycles);
+        Ok(cycles)
+    }
+
+    // Runs whole instructions until at least cycle_budget cycles have passed
+    // and returns the cycles actually run; the last instruction may go past
+    // the budget by a few cycles, which the caller takes from its next budget
+    // to keep in step with the rest of the console, as NES::run_frame does
+    pub fn execute_instructions(
+        &mut self,
+        bus: &mut dyn Bus,
+        cycle_budget: u64,
+    ) -> Result<u64, CpuError> {
         let mut n_cycles: u64 = 0_u64;
-        while n_cycles < n_instructions {
-            n_cycles += self.execute_next_instruction(ram);
+        while n_cycles < cycle_budget {
+            n_cycles += self.step(bus)?;
+        }
+        Ok(n_cycles)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Cycle {
+        Read(u16),
+        Write(u16, u8),
+    }
+
+    // Flat 64KB of memory logging every access of the CPU
+    struct RecordingBus {
+        memory: Vec<u8>,
+        cycles: Vec<Cycle>,
+    }
+
+    impl RecordingBus {
+        // The program is placed at 0x8000, where the reset vector points
+        fn new(program: &[u8]) -> RecordingBus {
+            let mut memory: Vec<u8> = vec![0; 0x10000];
+            memory[0x8000..0x8000 + program.len()].copy_from_slice(program);
+            memory[RESET_VECTOR as usize + 1] = 0x80;
+            RecordingBus {
+                memory,
+                cycles: Vec::new(),
+            }
         }
-        n_cycles
+    }
+
+    impl Bus for RecordingBus {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.cycles.push(Cycle::Read(addr));
+            self.memory[addr as usize]
+        }
+
+        fn write(&mut self, addr: u16, data: u8) {
+            self.cycles.push(Cycle::Write(addr, data));
+            self.memory[addr as usize] = data;
+        }
+    }
+
+    fn power_on(bus: &mut RecordingBus) -> CPU {
+        let cpu: CPU = CPU::from_bus(bus);
+        bus.cycles.clear();
+        cpu
+    }
+
+    #[test]
+    fn jsr_reads_the_stack_and_pushes_the_return_address_before_the_high_byte() {
+        let mut bus: RecordingBus = RecordingBus::new(&[0x20, 0x34, 0x12]);
+        let mut cpu: CPU = power_on(&mut bus);
+        assert_eq!(cpu.step(&mut bus).unwrap(), 6);
+        assert_eq!(
+            bus.cycles,
+            [
+                Cycle::Read(0x8000),
+                Cycle::Read(0x8001),
+                Cycle::Read(0x01FD),
+                Cycle::Write(0x01FD, 0x80),
+                Cycle::Write(0x01FC, 0x02),
+                Cycle::Read(0x8002),
+            ]
+        );
+        assert_eq!(cpu.pc(), 0x1234);
+    }
+
+    #[test]
+    fn lax_loads_a_and_x() {
+        let mut bus: RecordingBus = RecordingBus::new(&[0xA7, 0x10]);
+        bus.memory[0x10] = 0x85;
+        let mut cpu: CPU = power_on(&mut bus);
+        assert_eq!(cpu.step(&mut bus).unwrap(), 3);
+        assert_eq!((cpu.a(), cpu.x()), (0x85, 0x85));
+        assert!(cpu.flag(StatusFlag::Negative));
+        assert!(!cpu.flag(StatusFlag::Zero));
+    }
+
+    #[test]
+    fn dcp_decrements_memory_then_compares_it_with_a() {
+        // LDA #$42, DCP $10
+        let mut bus: RecordingBus = RecordingBus::new(&[0xA9, 0x42, 0xC7, 0x10]);
+        bus.memory[0x10] = 0x43;
+        let mut cpu: CPU = power_on(&mut bus);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.step(&mut bus).unwrap(), 5);
+        assert_eq!(bus.memory[0x10], 0x42);
+        assert_eq!(cpu.a(), 0x42);
+        assert!(cpu.flag(StatusFlag::Zero));
+        assert!(cpu.flag(StatusFlag::Carry));
+        assert!(!cpu.flag(StatusFlag::Negative));
+    }
+
+    #[test]
+    fn unofficial_zero_page_x_nop_skips_its_operand() {
+        // NOP $10,X then JAM
+        let mut bus: RecordingBus = RecordingBus::new(&[0x14, 0x10, 0x02]);
+        let mut cpu: CPU = power_on(&mut bus);
+        assert_eq!(cpu.step(&mut bus).unwrap(), 4);
+        assert_eq!(cpu.pc(), 0x8002);
+        assert!(matches!(cpu.step(&mut bus), Err(CpuError::Jammed(_))));
+        assert!(matches!(cpu.step(&mut bus), Err(CpuError::Jammed(_))));
+    }
+
+    #[test]
+    fn asl_accumulator_shifts_bit_7_into_carry() {
+        // LDA #$80, ASL A
+        let mut bus: RecordingBus = RecordingBus::new(&[0xA9, 0x80, 0x0A]);
+        let mut cpu: CPU = power_on(&mut bus);
+        cpu.step(&mut bus).unwrap();
+        bus.cycles.clear();
+        assert_eq!(cpu.step(&mut bus).unwrap(), 2);
+        assert_eq!(cpu.a(), 0);
+        assert!(cpu.flag(StatusFlag::Carry));
+        assert!(cpu.flag(StatusFlag::Zero));
+        assert!(!cpu.flag(StatusFlag::Negative));
+        // the accumulator form does not write to memory
+        assert!(!bus
+            .cycles
+            .iter()
+            .any(|cycle| matches!(cycle, Cycle::Write(..))));
+        assert_eq!(cpu.pc(), 0x8003);
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn decimal_mode_adc_and_sbc_use_bcd() {
+        // SED, CLC, LDA #$09, ADC #$01, SEC, SBC #$01
+        let mut bus: RecordingBus =
+            RecordingBus::new(&[0xF8, 0x18, 0xA9, 0x09, 0x69, 0x01, 0x38, 0xE9, 0x01]);
+        let mut cpu: CPU = power_on(&mut bus);
+        for _ in 0..4 {
+            cpu.step(&mut bus).unwrap();
+        }
+        assert_eq!(cpu.a(), 0x10);
+        assert!(!cpu.flag(StatusFlag::Carry));
+        cpu.step(&mut bus).unwrap();
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.a(), 0x09);
+        assert!(cpu.flag(StatusFlag::Carry));
+    }
+
+    #[test]
+    fn page_crossing_costs_a_cycle_for_reads_only() {
+        // LDX #$01, LDA $2000,X, LDA $20FF,X, STA $2000,X, STA $20FF,X
+        let mut bus: RecordingBus = RecordingBus::new(&[
+            0xA2, 0x01, 0xBD, 0x00, 0x20, 0xBD, 0xFF, 0x20, 0x9D, 0x00, 0x20, 0x9D, 0xFF, 0x20,
+        ]);
+        let mut cpu: CPU = power_on(&mut bus);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.step(&mut bus).unwrap(), 4);
+        bus.cycles.clear();
+        assert_eq!(cpu.step(&mut bus).unwrap(), 5);
+        // the address is read before the carry into its high byte is fixed up
+        assert_eq!(
+            bus.cycles,
+            [
+                Cycle::Read(0x8005),
+                Cycle::Read(0x8006),
+                Cycle::Read(0x8007),
+                Cycle::Read(0x2000),
+                Cycle::Read(0x2100),
+            ]
+        );
+        assert_eq!(cpu.step(&mut bus).unwrap(), 5);
+        assert_eq!(cpu.step(&mut bus).unwrap(), 5);
+    }
+
+    #[test]
+    fn fetching_past_0xffff_wraps_pc_to_0x0000() {
+        let mut bus: RecordingBus = RecordingBus::new(&[]);
+        // LDA #$42 with its operand at 0x0000
+        bus.memory[0xFFFF] = 0xA9;
+        bus.memory[0x0000] = 0x42;
+        let mut cpu: CPU = power_on(&mut bus);
+        cpu.set_pc(0xFFFF);
+        assert_eq!(cpu.step(&mut bus).unwrap(), 2);
+        assert_eq!(bus.cycles, [Cycle::Read(0xFFFF), Cycle::Read(0x0000)]);
+        assert_eq!((cpu.a(), cpu.pc()), (0x42, 0x0001));
+    }
+
+    #[test]
+    fn read_modify_write_writes_the_unmodified_value_first() {
+        let mut bus: RecordingBus = RecordingBus::new(&[0xE6, 0x10]);
+        bus.memory[0x10] = 0x41;
+        let mut cpu: CPU = power_on(&mut bus);
+        assert_eq!(cpu.step(&mut bus).unwrap(), 5);
+        assert_eq!(
+            bus.cycles,
+            [
+                Cycle::Read(0x8000),
+                Cycle::Read(0x8001),
+                Cycle::Read(0x0010),
+                Cycle::Write(0x0010, 0x41),
+                Cycle::Write(0x0010, 0x42),
+            ]
+        );
+    }
+
+    #[test]
+    fn php_and_brk_push_the_status_with_the_break_flag_and_bit_5_set() {
+        // PHP, BRK
+        let mut bus: RecordingBus = RecordingBus::new(&[0x08, 0x00]);
+        bus.memory[IRQ_VECTOR as usize + 1] = 0x90;
+        let mut cpu: CPU = power_on(&mut bus);
+        // only the interrupt disable flag is set after reset
+        assert_eq!(cpu.status().bits(), 0b00100100);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(bus.memory[0x01FD], 0b00110100);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(bus.memory[0x01FA..0x01FD], [0b00110100, 0x03, 0x80]);
+        assert_eq!(cpu.pc(), 0x9000);
+        assert_eq!(cpu.status().bits(), 0b00100100);
+    }
+
+    #[test]
+    fn plp_and_rti_ignore_the_break_flag_and_bit_5() {
+        // LDA #$FF, PHA, PLP, then the return address 0x8010 and a status
+        // with only the break flag set for RTI
+        let mut bus: RecordingBus = RecordingBus::new(&[
+            0xA9, 0xFF, 0x48, 0x28, 0xA9, 0x80, 0x48, 0xA9, 0x10, 0x48, 0x48, 0x40,
+        ]);
+        let mut cpu: CPU = power_on(&mut bus);
+        for _ in 0..3 {
+            cpu.step(&mut bus).unwrap();
+        }
+        assert_eq!(cpu.status().bits(), 0b11101111);
+        for _ in 0..6 {
+            cpu.step(&mut bus).unwrap();
+        }
+        assert_eq!(cpu.pc(), 0x8010);
+        assert_eq!(cpu.status().bits(), 0b00100000);
+    }
+
+    #[test]
+    fn stack_wraps_around_inside_page_1() {
+        // LDX #$00, TXS, LDA #$42, PHA, LDA #$00, PLA
+        let mut bus: RecordingBus =
+            RecordingBus::new(&[0xA2, 0x00, 0x9A, 0xA9, 0x42, 0x48, 0xA9, 0x00, 0x68]);
+        let mut cpu: CPU = power_on(&mut bus);
+        for _ in 0..3 {
+            cpu.step(&mut bus).unwrap();
+        }
+        assert_eq!(cpu.sp(), 0x00);
+        bus.cycles.clear();
+        cpu.step(&mut bus).unwrap();
+        assert!(bus.cycles.contains(&Cycle::Write(0x0100, 0x42)));
+        assert_eq!(cpu.sp(), 0xFF);
+        cpu.step(&mut bus).unwrap();
+        bus.cycles.clear();
+        cpu.step(&mut bus).unwrap();
+        assert!(bus.cycles.contains(&Cycle::Read(0x0100)));
+        assert_eq!((cpu.a(), cpu.sp()), (0x42, 0x00));
+    }
+
+    #[test]
+    fn opcode_info_describes_the_opcode() {
+        assert_eq!(
+            opcode_info(0xA9),
+            Some(OpcodeInfo {
+                opcode: 0xA9,
+                mnemonic: "LDA",
+                mode: AddressingMode::Immediate,
+                length: 2,
+                cycles: 2,
+                page_cross_penalty: false,
+                is_official: true,
+            })
+        );
+        assert_eq!(
+            opcode_info(0x6C),
+            Some(OpcodeInfo {
+                opcode: 0x6C,
+                mnemonic: "JMP",
+                mode: AddressingMode::Indirect,
+                length: 3,
+                cycles: 5,
+                page_cross_penalty: false,
+                is_official: true,
+            })
+        );
+        assert_eq!(
+            opcode_info(0xBD),
+            Some(OpcodeInfo {
+                opcode: 0xBD,
+                mnemonic: "LDA",
+                mode: AddressingMode::AbsoluteX,
+                length: 3,
+                cycles: 4,
+                page_cross_penalty: true,
+                is_official: true,
+            })
+        );
+        assert_eq!(
+            opcode_info(0x02),
+            Some(OpcodeInfo {
+                opcode: 0x02,
+                mnemonic: "JAM",
+                mode: AddressingMode::Implied,
+                length: 1,
+                cycles: 2,
+                page_cross_penalty: false,
+                is_official: false,
+            })
+        );
+        // the unstable opcodes are not implemented
+        assert_eq!(opcode_info(0x8B), None);
+    }
+
+    #[test]
+    fn subroutine_call_and_indirect_jump_cycles() {
+        // JSR $8009, JMP ($0010), JMP ($02FF), RTS
+        let mut bus: RecordingBus =
+            RecordingBus::new(&[0x20, 0x09, 0x80, 0x6C, 0x10, 0x00, 0x6C, 0xFF, 0x02, 0x60]);
+        bus.memory[0x0010..0x0012].copy_from_slice(&[0x06, 0x80]);
+        // the high byte of a pointer at the end of a page is read from its start
+        bus.memory[0x02FF] = 0x00;
+        bus.memory[0x0200] = 0x90;
+        let mut cpu: CPU = power_on(&mut bus);
+        let jsr: u64 = cpu.step(&mut bus).unwrap();
+        let rts: u64 = cpu.step(&mut bus).unwrap();
+        assert_eq!((cpu.pc(), jsr + rts), (0x8003, 12));
+        assert_eq!((cpu.step(&mut bus).unwrap(), cpu.pc()), (5, 0x8006));
+        assert_eq!((cpu.step(&mut bus).unwrap(), cpu.pc()), (5, 0x9000));
     }
 }
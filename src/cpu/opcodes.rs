@@ -0,0 +1,293 @@
+use super::{AddressingMode, CPU};
+use crate::bus::Bus;
+
+type Handler = fn(&mut CPU, &mut dyn Bus, &AddressingMode);
+
+#[derive(Clone, Copy)]
+pub(super) struct Opcode {
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    pub cycles: u64,
+    // whether crossing a page boundary while resolving the operand costs one more cycle
+    pub page_cross_penalty: bool,
+    pub handler: Handler,
+}
+
+const fn op(
+    mnemonic: &'static str,
+    mode: AddressingMode,
+    cycles: u64,
+    page_cross_penalty: bool,
+    handler: Handler,
+) -> Option<Opcode> {
+    Some(Opcode {
+        mnemonic,
+        mode,
+        cycles,
+        page_cross_penalty,
+        handler,
+    })
+}
+
+pub(super) static OPCODES: [Option<Opcode>; 256] = build_table();
+
+// Branches are listed without page-cross penalty as they handle their own extra cycles
+// Only the indexed reads have a page-cross penalty: the indexed stores and
+// read-modify-write instructions always take the cycle of the page crossing,
+// so STA $20FF,X takes 5 cycles whether or not X crosses the page
+// JSR and RTS take 6 cycles each, so a call and its return take 12; JMP
+// takes 3 cycles, 5 through a pointer wherever the pointer lies
+const fn build_table() -> [Option<Opcode>; 256] {
+    use AddressingMode::*;
+
+    let mut table: [Option<Opcode>; 256] = [None; 256];
+    table[0x00] = op("BRK", Implied, 7, false, CPU::brk);
+    table[0x01] = op("ORA", IndexedIndirect, 6, false, CPU::ora);
+    table[0x02] = op("JAM", Implied, 2, false, CPU::jam);
+    table[0x03] = op("SLO", IndexedIndirect, 8, false, CPU::slo);
+    table[0x04] = op("NOP", ZeroPage, 3, false, CPU::nop);
+    table[0x05] = op("ORA", ZeroPage, 3, false, CPU::ora);
+    table[0x06] = op("ASL", ZeroPage, 5, false, CPU::asl);
+    table[0x07] = op("SLO", ZeroPage, 5, false, CPU::slo);
+    table[0x08] = op("PHP", Implied, 3, false, CPU::php);
+    table[0x09] = op("ORA", Immediate, 2, false, CPU::ora);
+    table[0x0A] = op("ASL", Accumulator, 2, false, CPU::asl);
+    table[0x0B] = op("ANC", Immediate, 2, false, CPU::anc);
+    table[0x0C] = op("NOP", Absolute, 4, false, CPU::nop);
+    table[0x0D] = op("ORA", Absolute, 4, false, CPU::ora);
+    table[0x0E] = op("ASL", Absolute, 6, false, CPU::asl);
+    table[0x0F] = op("SLO", Absolute, 6, false, CPU::slo);
+    table[0x10] = op("BPL", Relative, 2, false, CPU::bpl);
+    table[0x11] = op("ORA", IndirectIndexed, 5, true, CPU::ora);
+    table[0x12] = op("JAM", Implied, 2, false, CPU::jam);
+    table[0x13] = op("SLO", IndirectIndexed, 8, false, CPU::slo);
+    table[0x14] = op("NOP", ZeroPageX, 4, false, CPU::nop);
+    table[0x15] = op("ORA", ZeroPageX, 4, false, CPU::ora);
+    table[0x16] = op("ASL", ZeroPageX, 6, false, CPU::asl);
+    table[0x17] = op("SLO", ZeroPageX, 6, false, CPU::slo);
+    table[0x18] = op("CLC", Implied, 2, false, CPU::clc);
+    table[0x19] = op("ORA", AbsoluteY, 4, true, CPU::ora);
+    table[0x1A] = op("NOP", Implied, 2, false, CPU::nop);
+    table[0x1B] = op("SLO", AbsoluteY, 7, false, CPU::slo);
+    table[0x1C] = op("NOP", AbsoluteX, 4, true, CPU::nop);
+    table[0x1D] = op("ORA", AbsoluteX, 4, true, CPU::ora);
+    table[0x1E] = op("ASL", AbsoluteX, 7, false, CPU::asl);
+    table[0x1F] = op("SLO", AbsoluteX, 7, false, CPU::slo);
+    table[0x20] = op("JSR", Absolute, 6, false, CPU::jsr);
+    table[0x21] = op("AND", IndexedIndirect, 6, false, CPU::and);
+    table[0x22] = op("JAM", Implied, 2, false, CPU::jam);
+    table[0x23] = op("RLA", IndexedIndirect, 8, false, CPU::rla);
+    table[0x24] = op("BIT", ZeroPage, 3, false, CPU::bit);
+    table[0x25] = op("AND", ZeroPage, 3, false, CPU::and);
+    table[0x26] = op("ROL", ZeroPage, 5, false, CPU::rol);
+    table[0x27] = op("RLA", ZeroPage, 5, false, CPU::rla);
+    table[0x28] = op("PLP", Implied, 4, false, CPU::plp);
+    table[0x29] = op("AND", Immediate, 2, false, CPU::and);
+    table[0x2A] = op("ROL", Accumulator, 2, false, CPU::rol);
+    table[0x2B] = op("ANC", Immediate, 2, false, CPU::anc);
+    table[0x2C] = op("BIT", Absolute, 4, false, CPU::bit);
+    table[0x2D] = op("AND", Absolute, 4, false, CPU::and);
+    table[0x2E] = op("ROL", Absolute, 6, false, CPU::rol);
+    table[0x2F] = op("RLA", Absolute, 6, false, CPU::rla);
+    table[0x30] = op("BMI", Relative, 2, false, CPU::bmi);
+    table[0x31] = op("AND", IndirectIndexed, 5, true, CPU::and);
+    table[0x32] = op("JAM", Implied, 2, false, CPU::jam);
+    table[0x33] = op("RLA", IndirectIndexed, 8, false, CPU::rla);
+    table[0x34] = op("NOP", ZeroPageX, 4, false, CPU::nop);
+    table[0x35] = op("AND", ZeroPageX, 4, false, CPU::and);
+    table[0x36] = op("ROL", ZeroPageX, 6, false, CPU::rol);
+    table[0x37] = op("RLA", ZeroPageX, 6, false, CPU::rla);
+    table[0x38] = op("SEC", Implied, 2, false, CPU::sec);
+    table[0x39] = op("AND", AbsoluteY, 4, true, CPU::and);
+    table[0x3A] = op("NOP", Implied, 2, false, CPU::nop);
+    table[0x3B] = op("RLA", AbsoluteY, 7, false, CPU::rla);
+    table[0x3C] = op("NOP", AbsoluteX, 4, true, CPU::nop);
+    table[0x3D] = op("AND", AbsoluteX, 4, true, CPU::and);
+    table[0x3E] = op("ROL", AbsoluteX, 7, false, CPU::rol);
+    table[0x3F] = op("RLA", AbsoluteX, 7, false, CPU::rla);
+    table[0x40] = op("RTI", Implied, 6, false, CPU::rti);
+    table[0x41] = op("EOR", IndexedIndirect, 6, false, CPU::eor);
+    table[0x42] = op("JAM", Implied, 2, false, CPU::jam);
+    table[0x43] = op("SRE", IndexedIndirect, 8, false, CPU::sre);
+    table[0x44] = op("NOP", ZeroPage, 3, false, CPU::nop);
+    table[0x45] = op("EOR", ZeroPage, 3, false, CPU::eor);
+    table[0x46] = op("LSR", ZeroPage, 5, false, CPU::lsr);
+    table[0x47] = op("SRE", ZeroPage, 5, false, CPU::sre);
+    table[0x48] = op("PHA", Implied, 3, false, CPU::pha);
+    table[0x49] = op("EOR", Immediate, 2, false, CPU::eor);
+    table[0x4A] = op("LSR", Accumulator, 2, false, CPU::lsr);
+    table[0x4B] = op("ALR", Immediate, 2, false, CPU::alr);
+    table[0x4C] = op("JMP", Absolute, 3, false, CPU::jmp);
+    table[0x4D] = op("EOR", Absolute, 4, false, CPU::eor);
+    table[0x4E] = op("LSR", Absolute, 6, false, CPU::lsr);
+    table[0x4F] = op("SRE", Absolute, 6, false, CPU::sre);
+    table[0x50] = op("BVC", Relative, 2, false, CPU::bvc);
+    table[0x51] = op("EOR", IndirectIndexed, 5, true, CPU::eor);
+    table[0x52] = op("JAM", Implied, 2, false, CPU::jam);
+    table[0x53] = op("SRE", IndirectIndexed, 8, false, CPU::sre);
+    table[0x54] = op("NOP", ZeroPageX, 4, false, CPU::nop);
+    table[0x55] = op("EOR", ZeroPageX, 4, false, CPU::eor);
+    table[0x56] = op("LSR", ZeroPageX, 6, false, CPU::lsr);
+    table[0x57] = op("SRE", ZeroPageX, 6, false, CPU::sre);
+    table[0x58] = op("CLI", Implied, 2, false, CPU::cli);
+    table[0x59] = op("EOR", AbsoluteY, 4, true, CPU::eor);
+    table[0x5A] = op("NOP", Implied, 2, false, CPU::nop);
+    table[0x5B] = op("SRE", AbsoluteY, 7, false, CPU::sre);
+    table[0x5C] = op("NOP", AbsoluteX, 4, true, CPU::nop);
+    table[0x5D] = op("EOR", AbsoluteX, 4, true, CPU::eor);
+    table[0x5E] = op("LSR", AbsoluteX, 7, false, CPU::lsr);
+    table[0x5F] = op("SRE", AbsoluteX, 7, false, CPU::sre);
+    table[0x60] = op("RTS", Implied, 6, false, CPU::rts);
+    table[0x61] = op("ADC", IndexedIndirect, 6, false, CPU::adc);
+    table[0x62] = op("JAM", Implied, 2, false, CPU::jam);
+    table[0x63] = op("RRA", IndexedIndirect, 8, false, CPU::rra);
+    table[0x64] = op("NOP", ZeroPage, 3, false, CPU::nop);
+    table[0x65] = op("ADC", ZeroPage, 3, false, CPU::adc);
+    table[0x66] = op("ROR", ZeroPage, 5, false, CPU::ror);
+    table[0x67] = op("RRA", ZeroPage, 5, false, CPU::rra);
+    table[0x68] = op("PLA", Implied, 4, false, CPU::pla);
+    table[0x69] = op("ADC", Immediate, 2, false, CPU::adc);
+    table[0x6A] = op("ROR", Accumulator, 2, false, CPU::ror);
+    table[0x6B] = op("ARR", Immediate, 2, false, CPU::arr);
+    table[0x6C] = op("JMP", Indirect, 5, false, CPU::jmp);
+    table[0x6D] = op("ADC", Absolute, 4, false, CPU::adc);
+    table[0x6E] = op("ROR", Absolute, 6, false, CPU::ror);
+    table[0x6F] = op("RRA", Absolute, 6, false, CPU::rra);
+    table[0x70] = op("BVS", Relative, 2, false, CPU::bvs);
+    table[0x71] = op("ADC", IndirectIndexed, 5, true, CPU::adc);
+    table[0x72] = op("JAM", Implied, 2, false, CPU::jam);
+    table[0x73] = op("RRA", IndirectIndexed, 8, false, CPU::rra);
+    table[0x74] = op("NOP", ZeroPageX, 4, false, CPU::nop);
+    table[0x75] = op("ADC", ZeroPageX, 4, false, CPU::adc);
+    table[0x76] = op("ROR", ZeroPageX, 6, false, CPU::ror);
+    table[0x77] = op("RRA", ZeroPageX, 6, false, CPU::rra);
+    table[0x78] = op("SEI", Implied, 2, false, CPU::sei);
+    table[0x79] = op("ADC", AbsoluteY, 4, true, CPU::adc);
+    table[0x7A] = op("NOP", Implied, 2, false, CPU::nop);
+    table[0x7B] = op("RRA", AbsoluteY, 7, false, CPU::rra);
+    table[0x7C] = op("NOP", AbsoluteX, 4, true, CPU::nop);
+    table[0x7D] = op("ADC", AbsoluteX, 4, true, CPU::adc);
+    table[0x7E] = op("ROR", AbsoluteX, 7, false, CPU::ror);
+    table[0x7F] = op("RRA", AbsoluteX, 7, false, CPU::rra);
+    table[0x80] = op("NOP", Immediate, 2, false, CPU::nop);
+    table[0x81] = op("STA", IndexedIndirect, 6, false, CPU::sta);
+    table[0x82] = op("NOP", Immediate, 2, false, CPU::nop);
+    table[0x83] = op("SAX", IndexedIndirect, 6, false, CPU::sax);
+    table[0x84] = op("STY", ZeroPage, 3, false, CPU::sty);
+    table[0x85] = op("STA", ZeroPage, 3, false, CPU::sta);
+    table[0x86] = op("STX", ZeroPage, 3, false, CPU::stx);
+    table[0x87] = op("SAX", ZeroPage, 3, false, CPU::sax);
+    table[0x88] = op("DEY", Implied, 2, false, CPU::dey);
+    table[0x89] = op("NOP", Immediate, 2, false, CPU::nop);
+    table[0x8A] = op("TXA", Implied, 2, false, CPU::txa);
+    table[0x8C] = op("STY", Absolute, 4, false, CPU::sty);
+    table[0x8D] = op("STA", Absolute, 4, false, CPU::sta);
+    table[0x8E] = op("STX", Absolute, 4, false, CPU::stx);
+    table[0x8F] = op("SAX", Absolute, 4, false, CPU::sax);
+    table[0x90] = op("BCC", Relative, 2, false, CPU::bcc);
+    table[0x91] = op("STA", IndirectIndexed, 6, false, CPU::sta);
+    table[0x92] = op("JAM", Implied, 2, false, CPU::jam);
+    table[0x94] = op("STY", ZeroPageX, 4, false, CPU::sty);
+    table[0x95] = op("STA", ZeroPageX, 4, false, CPU::sta);
+    table[0x96] = op("STX", ZeroPageY, 4, false, CPU::stx);
+    table[0x97] = op("SAX", ZeroPageY, 4, false, CPU::sax);
+    table[0x98] = op("TYA", Implied, 2, false, CPU::tya);
+    table[0x99] = op("STA", AbsoluteY, 5, false, CPU::sta);
+    table[0x9A] = op("TXS", Implied, 2, false, CPU::txs);
+    table[0x9D] = op("STA", AbsoluteX, 5, false, CPU::sta);
+    table[0xA0] = op("LDY", Immediate, 2, false, CPU::ldy);
+    table[0xA1] = op("LDA", IndexedIndirect, 6, false, CPU::lda);
+    table[0xA2] = op("LDX", Immediate, 2, false, CPU::ldx);
+    table[0xA3] = op("LAX", IndexedIndirect, 6, false, CPU::lax);
+    table[0xA4] = op("LDY", ZeroPage, 3, false, CPU::ldy);
+    table[0xA5] = op("LDA", ZeroPage, 3, false, CPU::lda);
+    table[0xA6] = op("LDX", ZeroPage, 3, false, CPU::ldx);
+    table[0xA7] = op("LAX", ZeroPage, 3, false, CPU::lax);
+    table[0xA8] = op("TAY", Implied, 2, false, CPU::tay);
+    table[0xA9] = op("LDA", Immediate, 2, false, CPU::lda);
+    table[0xAA] = op("TAX", Implied, 2, false, CPU::tax);
+    table[0xAC] = op("LDY", Absolute, 4, false, CPU::ldy);
+    table[0xAD] = op("LDA", Absolute, 4, false, CPU::lda);
+    table[0xAE] = op("LDX", Absolute, 4, false, CPU::ldx);
+    table[0xAF] = op("LAX", Absolute, 4, false, CPU::lax);
+    table[0xB0] = op("BCS", Relative, 2, false, CPU::bcs);
+    table[0xB1] = op("LDA", IndirectIndexed, 5, true, CPU::lda);
+    table[0xB2] = op("JAM", Implied, 2, false, CPU::jam);
+    table[0xB3] = op("LAX", IndirectIndexed, 5, true, CPU::lax);
+    table[0xB4] = op("LDY", ZeroPageX, 4, false, CPU::ldy);
+    table[0xB5] = op("LDA", ZeroPageX, 4, false, CPU::lda);
+    table[0xB6] = op("LDX", ZeroPageY, 4, false, CPU::ldx);
+    table[0xB7] = op("LAX", ZeroPageY, 4, false, CPU::lax);
+    table[0xB8] = op("CLV", Implied, 2, false, CPU::clv);
+    table[0xB9] = op("LDA", AbsoluteY, 4, true, CPU::lda);
+    table[0xBA] = op("TSX", Implied, 2, false, CPU::tsx);
+    table[0xBC] = op("LDY", AbsoluteX, 4, true, CPU::ldy);
+    table[0xBD] = op("LDA", AbsoluteX, 4, true, CPU::lda);
+    table[0xBE] = op("LDX", AbsoluteY, 4, true, CPU::ldx);
+    table[0xBF] = op("LAX", AbsoluteY, 4, true, CPU::lax);
+    table[0xC0] = op("CPY", Immediate, 2, false, CPU::cpy);
+    table[0xC1] = op("CMP", IndexedIndirect, 6, false, CPU::cmp);
+    table[0xC2] = op("NOP", Immediate, 2, false, CPU::nop);
+    table[0xC3] = op("DCP", IndexedIndirect, 8, false, CPU::dcp);
+    table[0xC4] = op("CPY", ZeroPage, 3, false, CPU::cpy);
+    table[0xC5] = op("CMP", ZeroPage, 3, false, CPU::cmp);
+    table[0xC6] = op("DEC", ZeroPage, 5, false, CPU::dec);
+    table[0xC7] = op("DCP", ZeroPage, 5, false, CPU::dcp);
+    table[0xC8] = op("INY", Implied, 2, false, CPU::iny);
+    table[0xC9] = op("CMP", Immediate, 2, false, CPU::cmp);
+    table[0xCA] = op("DEX", Implied, 2, false, CPU::dex);
+    table[0xCB] = op("AXS", Immediate, 2, false, CPU::axs);
+    table[0xCC] = op("CPY", Absolute, 4, false, CPU::cpy);
+    table[0xCD] = op("CMP", Absolute, 4, false, CPU::cmp);
+    table[0xCE] = op("DEC", Absolute, 6, false, CPU::dec);
+    table[0xCF] = op("DCP", Absolute, 6, false, CPU::dcp);
+    table[0xD0] = op("BNE", Relative, 2, false, CPU::bne);
+    table[0xD1] = op("CMP", IndirectIndexed, 5, true, CPU::cmp);
+    table[0xD2] = op("JAM", Implied, 2, false, CPU::jam);
+    table[0xD3] = op("DCP", IndirectIndexed, 8, false, CPU::dcp);
+    table[0xD4] = op("NOP", ZeroPageX, 4, false, CPU::nop);
+    table[0xD5] = op("CMP", ZeroPageX, 4, false, CPU::cmp);
+    table[0xD6] = op("DEC", ZeroPageX, 6, false, CPU::dec);
+    table[0xD7] = op("DCP", ZeroPageX, 6, false, CPU::dcp);
+    table[0xD8] = op("CLD", Implied, 2, false, CPU::cld);
+    table[0xD9] = op("CMP", AbsoluteY, 4, true, CPU::cmp);
+    table[0xDA] = op("NOP", Implied, 2, false, CPU::nop);
+    table[0xDB] = op("DCP", AbsoluteY, 7, false, CPU::dcp);
+    table[0xDC] = op("NOP", AbsoluteX, 4, true, CPU::nop);
+    table[0xDD] = op("CMP", AbsoluteX, 4, true, CPU::cmp);
+    table[0xDE] = op("DEC", AbsoluteX, 7, false, CPU::dec);
+    table[0xDF] = op("DCP", AbsoluteX, 7, false, CPU::dcp);
+    table[0xE0] = op("CPX", Immediate, 2, false, CPU::cpx);
+    table[0xE1] = op("SBC", IndexedIndirect, 6, false, CPU::sbc);
+    table[0xE2] = op("NOP", Immediate, 2, false, CPU::nop);
+    table[0xE3] = op("ISC", IndexedIndirect, 8, false, CPU::isc);
+    table[0xE4] = op("CPX", ZeroPage, 3, false, CPU::cpx);
+    table[0xE5] = op("SBC", ZeroPage, 3, false, CPU::sbc);
+    table[0xE6] = op("INC", ZeroPage, 5, false, CPU::inc);
+    table[0xE7] = op("ISC", ZeroPage, 5, false, CPU::isc);
+    table[0xE8] = op("INX", Implied, 2, false, CPU::inx);
+    table[0xE9] = op("SBC", Immediate, 2, false, CPU::sbc);
+    table[0xEA] = op("NOP", Implied, 2, false, CPU::nop);
+    table[0xEB] = op("SBC", Immediate, 2, false, CPU::sbc);
+    table[0xEC] = op("CPX", Absolute, 4, false, CPU::cpx);
+    table[0xED] = op("SBC", Absolute, 4, false, CPU::sbc);
+    table[0xEE] = op("INC", Absolute, 6, false, CPU::inc);
+    table[0xEF] = op("ISC", Absolute, 6, false, CPU::isc);
+    table[0xF0] = op("BEQ", Relative, 2, false, CPU::beq);
+    table[0xF1] = op("SBC", IndirectIndexed, 5, true, CPU::sbc);
+    table[0xF2] = op("JAM", Implied, 2, false, CPU::jam);
+    table[0xF3] = op("ISC", IndirectIndexed, 8, false, CPU::isc);
+    table[0xF4] = op("NOP", ZeroPageX, 4, false, CPU::nop);
+    table[0xF5] = op("SBC", ZeroPageX, 4, false, CPU::sbc);
+    table[0xF6] = op("INC", ZeroPageX, 6, false, CPU::inc);
+    table[0xF7] = op("ISC", ZeroPageX, 6, false, CPU::isc);
+    table[0xF8] = op("SED", Implied, 2, false, CPU::sed);
+    table[0xF9] = op("SBC", AbsoluteY, 4, true, CPU::sbc);
+    table[0xFA] = op("NOP", Implied, 2, false, CPU::nop);
+    table[0xFB] = op("ISC", AbsoluteY, 7, false, CPU::isc);
+    table[0xFC] = op("NOP", AbsoluteX, 4, true, CPU::nop);
+    table[0xFD] = op("SBC", AbsoluteX, 4, true, CPU::sbc);
+    table[0xFE] = op("INC", AbsoluteX, 7, false, CPU::inc);
+    table[0xFF] = op("ISC", AbsoluteX, 7, false, CPU::isc);
+    table
+}
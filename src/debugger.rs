@@ -0,0 +1,123 @@
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{
+    cpu::{CpuError, CpuState},
+    mem::{CARTRIDGE_START, PPU_REG_START},
+    nes::NES,
+    ram::{WatchHit, RAM},
+};
+
+const HEX_DUMP_LINE_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint(u16),
+    // watchpoints are set on the RAM, see RAM::add_watchpoint
+    Watchpoint(WatchHit),
+    StepBudgetExhausted,
+}
+
+// Why the execution stopped and the registers at that point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stop {
+    pub reason: StopReason,
+    pub state: CpuState,
+}
+
+// Runs a NES instruction by instruction, halting when the PC reaches a breakpoint
+// or after an instruction that accessed a watched address
+#[derive(Debug, Default)]
+pub struct Debugger {
+    // breakpoint address -> enabled
+    breakpoints: HashMap<u16, bool>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr, true);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    // Keeps the breakpoint around but ignores it until it is enabled again
+    pub fn set_breakpoint_enabled(&mut self, addr: u16, enabled: bool) {
+        if let Some(breakpoint) = self.breakpoints.get_mut(&addr) {
+            *breakpoint = enabled;
+        }
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (u16, bool)> + '_ {
+        self.breakpoints
+            .iter()
+            .map(|(&addr, &enabled)| (addr, enabled))
+    }
+
+    fn is_break(&self, addr: u16) -> bool {
+        self.breakpoints.get(&addr).copied().unwrap_or(false)
+    }
+
+    // Steps until the PC hits an enabled breakpoint or max_steps instructions
+    // have been executed. The instruction at the current PC is always executed,
+    // so that calling it again resumes from a breakpoint.
+    pub fn run_until_break(&self, nes: &mut NES, max_steps: u64) -> Result<Stop, CpuError> {
+        // forget accesses made outside of the execution, e.g. by a frontend
+        nes.ram_mut().take_watch_hit();
+        for _ in 0..max_steps {
+            nes.step()?;
+            if let Some(hit) = nes.ram_mut().take_watch_hit() {
+                return Ok(Stop {
+                    reason: StopReason::Watchpoint(hit),
+                    state: nes.cpu().state(),
+                });
+            }
+            let pc: u16 = nes.cpu().pc();
+            if self.is_break(pc) {
+                return Ok(Stop {
+                    reason: StopReason::Breakpoint(pc),
+                    state: nes.cpu().state(),
+                });
+            }
+        }
+        Ok(Stop {
+            reason: StopReason::StepBudgetExhausted,
+            state: nes.cpu().state(),
+        })
+    }
+}
+
+// Formats len bytes of memory from start, 16 per line:
+// 0300: 00 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F  ................
+// The registers are not read and are shown as --, see RAM::peek
+pub fn hex_dump(ram: &RAM, start: u16, len: usize) -> String {
+    let bytes: Vec<u8> = ram.dump(start, len);
+    let mut output: String = String::new();
+    for (line, chunk) in bytes.chunks(HEX_DUMP_LINE_SIZE).enumerate() {
+        let line_start: u16 = start.wrapping_add((line * HEX_DUMP_LINE_SIZE) as u16);
+        let mut text: String = String::new();
+        write!(output, "{:04X}:", line_start).unwrap();
+        for (i, &byte) in chunk.iter().enumerate() {
+            let addr: u16 = line_start.wrapping_add(i as u16);
+            if (PPU_REG_START..CARTRIDGE_START).contains(&addr) {
+                output.push_str(" --");
+                text.push(' ');
+            } else {
+                write!(output, " {:02X}", byte).unwrap();
+                text.push(if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                });
+            }
+        }
+        // keeps the text column aligned on a short last line
+        let padding: usize = (HEX_DUMP_LINE_SIZE - chunk.len()) * 3;
+        writeln!(output, "{:padding$}  {}", "", text, padding = padding).unwrap();
+    }
+    output
+}
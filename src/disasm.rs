@@ -0,0 +1,129 @@
+use crate::{
+    cpu::{opcode_info, AddressingMode, OpcodeInfo},
+    ram::RAM,
+};
+
+// Assembly of the instruction at addr, e.g. "LDA $0200,X", read with
+// RAM::peek so that nothing is executed or modified; the bytes that are not
+// opcodes are shown as ".byte $XX". Relative branches show their target.
+pub fn disassemble(ram: &RAM, addr: u16) -> String {
+    disassemble_with_labels(ram, addr, |_| None)
+}
+
+// Same as disassemble, with the absolute addresses and branch targets that
+// label names shown as that name, e.g. "JSR init"
+pub fn disassemble_with_labels(
+    ram: &RAM,
+    addr: u16,
+    label: impl Fn(u16) -> Option<String>,
+) -> String {
+    let opcode: u8 = ram.peek(addr);
+    let Some(info) = opcode_info(opcode) else {
+        return format!(".byte ${:02X}", opcode);
+    };
+    let operand: String = format_operand(ram, addr, &info, label);
+    if operand.is_empty() {
+        info.mnemonic.to_string()
+    } else {
+        format!("{} {}", info.mnemonic, operand)
+    }
+}
+
+// Number of bytes of the instruction at addr, 1 for the bytes that are not opcodes
+pub fn instruction_length(ram: &RAM, addr: u16) -> u8 {
+    opcode_info(ram.peek(addr)).map_or(1, |info| info.length)
+}
+
+fn format_operand(
+    ram: &RAM,
+    addr: u16,
+    info: &OpcodeInfo,
+    label: impl Fn(u16) -> Option<String>,
+) -> String {
+    let low: u8 = ram.peek(addr.wrapping_add(1));
+    let word: u16 = u16::from_le_bytes([low, ram.peek(addr.wrapping_add(2))]);
+    let address = |target: u16| label(target).unwrap_or_else(|| format!("${:04X}", target));
+    match info.mode {
+        AddressingMode::Accumulator => "A".to_string(),
+        AddressingMode::Implied => String::new(),
+        AddressingMode::Absolute => address(word),
+        AddressingMode::AbsoluteX => format!("{},X", address(word)),
+        AddressingMode::AbsoluteY => format!("{},Y", address(word)),
+        AddressingMode::Immediate => format!("#${:02X}", low),
+        AddressingMode::Indirect => format!("(${:04X})", word),
+        AddressingMode::IndexedIndirect => format!("(${:02X},X)", low),
+        AddressingMode::IndirectIndexed => format!("(${:02X}),Y", low),
+        AddressingMode::Relative => address(addr.wrapping_add(2).wrapping_add(low as i8 as u16)),
+        AddressingMode::ZeroPage => format!("${:02X}", low),
+        AddressingMode::ZeroPageX => format!("${:02X},X", low),
+        AddressingMode::ZeroPageY => format!("${:02X},Y", low),
+    }
+}
+
+// The address and assembly of the instructions laid out from a given address,
+// each one following the bytes of the previous one; it wraps around after
+// 0xFFFF and never ends, take the instructions needed:
+// InstructionIter::new(nes.ram(), nes.cpu().pc()).take(10)
+#[derive(Debug, Clone)]
+pub struct InstructionIter<'a> {
+    ram: &'a RAM,
+    addr: u16,
+}
+
+impl<'a> InstructionIter<'a> {
+    pub fn new(ram: &'a RAM, addr: u16) -> InstructionIter<'a> {
+        InstructionIter { ram, addr }
+    }
+}
+
+impl Iterator for InstructionIter<'_> {
+    type Item = (u16, String);
+
+    fn next(&mut self) -> Option<(u16, String)> {
+        let addr: u16 = self.addr;
+        self.addr = addr.wrapping_add(instruction_length(self.ram, addr) as u16);
+        Some((addr, disassemble(self.ram, addr)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom;
+
+    #[test]
+    fn instruction_iter_follows_the_instruction_lengths() {
+        // LDA #$42, STA $0200,X, BNE to the STA, then the NOP filler
+        let ram: RAM =
+            RAM::from_bytes(&test_rom::nrom(&[0xA9, 0x42, 0x9D, 0x00, 0x02, 0xD0, 0xFB])).unwrap();
+        let instructions: Vec<(u16, String)> = InstructionIter::new(&ram, 0x8000).take(4).collect();
+        assert_eq!(
+            instructions,
+            [
+                (0x8000, "LDA #$42".to_string()),
+                (0x8002, "STA $0200,X".to_string()),
+                (0x8005, "BNE $8002".to_string()),
+                (0x8007, "NOP".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_replace_the_addresses_they_name() {
+        // JSR $8006, STA $0200,X, BNE to the STA, LDA $0300
+        let ram: RAM = RAM::from_bytes(&test_rom::nrom(&[
+            0x20, 0x06, 0x80, 0x9D, 0x00, 0x02, 0xD0, 0xFB, 0xAD, 0x00, 0x03,
+        ]))
+        .unwrap();
+        let label = |addr: u16| match addr {
+            0x8006 => Some("init".to_string()),
+            0x8003 => Some("store".to_string()),
+            0x0200 => Some("buffer".to_string()),
+            _ => None,
+        };
+        assert_eq!(disassemble_with_labels(&ram, 0x8000, label), "JSR init");
+        assert_eq!(disassemble_with_labels(&ram, 0x8003, label), "STA buffer,X");
+        assert_eq!(disassemble_with_labels(&ram, 0x8006, label), "BNE store");
+        assert_eq!(disassemble_with_labels(&ram, 0x8008, label), "LDA $0300");
+    }
+}
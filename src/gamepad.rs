@@ -0,0 +1,127 @@
+use std::{error::Error, fmt};
+
+use gilrs::{EventType, GamepadId, Gilrs};
+
+use crate::controller::{Button, ControllerState, KeyMap};
+
+// Gamepad buttons are bound per port, the first pad connected driving
+// controller 1 and the second one controller 2
+const DEFAULT_BUTTONS: [(gilrs::Button, Button); 8] = [
+    (gilrs::Button::East, Button::A),
+    (gilrs::Button::South, Button::B),
+    (gilrs::Button::Select, Button::Select),
+    (gilrs::Button::Start, Button::Start),
+    (gilrs::Button::DPadUp, Button::Up),
+    (gilrs::Button::DPadDown, Button::Down),
+    (gilrs::Button::DPadLeft, Button::Left),
+    (gilrs::Button::DPadRight, Button::Right),
+];
+
+#[derive(Debug)]
+pub enum GamepadError {
+    // gilrs has no backend for this platform
+    Unsupported,
+    Init(String),
+}
+
+impl fmt::Display for GamepadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GamepadError::Unsupported => write!(f, "Gamepads are not supported on this platform"),
+            GamepadError::Init(err) => write!(f, "Could not initialize the gamepads: {}", err),
+        }
+    }
+}
+
+impl Error for GamepadError {}
+
+// Polls the connected gamepads and turns them into controller states
+pub struct Gamepads {
+    gilrs: Gilrs,
+    // pad plugged in each controller port
+    ports: [Option<GamepadId>; 2],
+    // keys are the port of a pad and one of its buttons
+    key_map: KeyMap<(u8, gilrs::Button)>,
+}
+
+impl Gamepads {
+    pub fn new() -> Result<Gamepads, GamepadError> {
+        let mut key_map: KeyMap<(u8, gilrs::Button)> = KeyMap::new();
+        for port in 0..2 {
+            for (pad_button, button) in DEFAULT_BUTTONS {
+                key_map.bind((port, pad_button), port, button);
+            }
+        }
+        let mut gamepads: Gamepads = Gamepads {
+            gilrs: Gilrs::new().map_err(|err| match err {
+                gilrs::Error::NotImplemented(_) => GamepadError::Unsupported,
+                err => GamepadError::Init(err.to_string()),
+            })?,
+            ports: [None; 2],
+            key_map,
+        };
+        let connected: Vec<GamepadId> = gamepads.gilrs.gamepads().map(|(id, _)| id).collect();
+        for id in connected {
+            gamepads.connect(id);
+        }
+        Ok(gamepads)
+    }
+
+    // Replaces the default bindings, keys being a port and a gamepad button
+    pub fn set_key_map(&mut self, key_map: KeyMap<(u8, gilrs::Button)>) {
+        self.key_map = key_map;
+    }
+
+    pub fn key_map_mut(&mut self) -> &mut KeyMap<(u8, gilrs::Button)> {
+        &mut self.key_map
+    }
+
+    // Plugs a pad in the first free port
+    fn connect(&mut self, id: GamepadId) {
+        if self.ports.contains(&Some(id)) {
+            return;
+        }
+        if let Some(port) = self.ports.iter_mut().find(|port| port.is_none()) {
+            *port = Some(id);
+        }
+    }
+
+    // Handles the pads connected or disconnected since the last call, to be
+    // called once per frame
+    pub fn update(&mut self) {
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::Connected => self.connect(event.id),
+                EventType::Disconnected => {
+                    for port in &mut self.ports {
+                        if *port == Some(event.id) {
+                            *port = None;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn is_pressed(&self, port: u8, button: gilrs::Button) -> bool {
+        self.ports[port as usize]
+            .and_then(|id| self.gilrs.connected_gamepad(id))
+            .is_some_and(|gamepad| gamepad.is_pressed(button))
+    }
+
+    // Buttons of the controller in port held on the gamepads
+    pub fn controller_state(&self, port: u8) -> ControllerState {
+        self.key_map.controller_state(port, |&(pad_port, button)| {
+            self.is_pressed(pad_port, button)
+        })
+    }
+}
+
+impl fmt::Debug for Gamepads {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Gamepads")
+            .field("ports", &self.ports)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,35 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![allow(clippy::upper_case_acronyms)]
+
+#[cfg(feature = "apu")]
+pub mod apu;
+#[cfg(feature = "audio")]
+pub mod audio;
+pub mod bitfield;
+pub mod bus;
+#[cfg(feature = "std")]
+pub mod cartridge;
+#[cfg(feature = "std")]
+pub mod controller;
+pub mod cpu;
+#[cfg(feature = "std")]
+pub mod debugger;
+#[cfg(feature = "std")]
+pub mod disasm;
+#[cfg(feature = "gamepad")]
+pub mod gamepad;
+pub mod mem;
+#[cfg(feature = "std")]
+pub mod movie;
+#[cfg(feature = "std")]
+pub mod nes;
+#[cfg(feature = "std")]
+pub mod ppu;
+#[cfg(feature = "std")]
+pub mod ram;
+#[cfg(feature = "std")]
+pub mod rewind;
+#[cfg(all(test, feature = "std"))]
+mod test_rom;
+#[cfg(feature = "wasm")]
+pub mod wasm;
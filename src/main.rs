@@ -1,18 +1,56 @@
 use std::env;
 
-use nes::NES;
-
-mod bitfield;
-mod cpu;
-mod nes;
-mod ram;
+#[cfg(feature = "audio")]
+use nes_emulator::audio::Audio;
+#[cfg(feature = "gamepad")]
+use nes_emulator::gamepad::Gamepads;
+use nes_emulator::{
+    cartridge::Region,
+    cpu::CpuError,
+    nes::{NesBuilder, NES},
+};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: cargo run -- <rom_file>");
+    // the region of the ROM header unless given
+    let region: Option<Region> = match args.get(2).map(String::as_str) {
+        _ if args.len() < 2 || args.len() > 3 => usage(),
+        None => None,
+        Some("ntsc") => Some(Region::Ntsc),
+        Some("pal") => Some(Region::Pal),
+        _ => usage(),
+    };
+    let mut builder: NesBuilder = NesBuilder::from_file(&args[1]).trace(true);
+    if let Some(region) = region {
+        builder = builder.region(region);
+    }
+    let mut nes: NES = match builder.build() {
+        Ok(nes) => nes,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    #[cfg(feature = "audio")]
+    match Audio::new() {
+        Ok(audio) => nes.set_audio(audio),
+        Err(err) => eprintln!("{}, running without sound", err),
+    }
+    #[cfg(feature = "gamepad")]
+    match Gamepads::new() {
+        Ok(gamepads) => nes.set_gamepads(gamepads),
+        Err(err) => eprintln!("{}, running without gamepads", err),
+    }
+    let result: Result<(), CpuError> = nes.run();
+    // flush the save file before exiting
+    drop(nes);
+    if let Err(err) = result {
+        eprintln!("{}", err);
         std::process::exit(1);
     }
-    let mut nes: NES = NES::new(&args[1]);
-    nes.run();
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: cargo run -- <rom_file> [ntsc|pal]");
+    std::process::exit(1);
 }
@@ -0,0 +1,46 @@
+// Addresses of the CPU memory map, the memory map in ram.rs describes what
+// each range is connected to
+
+// 2KB of internal RAM, mirrored up to 0x1FFF
+pub const WORK_RAM_SIZE: usize = 0x0800;
+pub const WORK_RAM_END: u16 = 0x1FFF;
+// the stack lives in page 1 of the internal RAM, 0x0100 - 0x01FF
+pub const STACK_BASE: u16 = 0x0100;
+
+// PPU registers, mirrored every 8 bytes up to 0x3FFF
+pub const PPU_REG_START: u16 = 0x2000;
+pub const PPU_REG_END: u16 = 0x3FFF;
+pub const PPU_REG_COUNT: u16 = 8;
+pub const PPUCTRL: u16 = 0x2000;
+pub const PPUMASK: u16 = 0x2001;
+pub const PPUSTATUS: u16 = 0x2002;
+pub const OAMADDR: u16 = 0x2003;
+pub const OAMDATA: u16 = 0x2004;
+pub const PPUSCROLL: u16 = 0x2005;
+pub const PPUADDR: u16 = 0x2006;
+pub const PPUDATA: u16 = 0x2007;
+
+// APU and I/O registers
+pub const APU_REG_START: u16 = 0x4000;
+// last register of the sound channels
+pub const APU_CHANNELS_END: u16 = 0x4013;
+pub const OAM_DMA: u16 = 0x4014;
+pub const APU_STATUS: u16 = 0x4015;
+pub const JOY1: u16 = 0x4016;
+// the frame counter on writes, controller 2 on reads
+pub const JOY2: u16 = 0x4017;
+pub const APU_FRAME_COUNTER: u16 = 0x4017;
+// test mode registers, then nothing until the cartridge
+pub const UNMAPPED_START: u16 = 0x4018;
+
+// Cartridge space
+pub const CARTRIDGE_START: u16 = 0x6000;
+pub const PRG_RAM_START: u16 = 0x6000;
+// the 512-byte trainer is loaded in PRG RAM
+pub const TRAINER_START: u16 = 0x7000;
+pub const PRG_ROM_START: u16 = 0x8000;
+
+// Interrupt vectors, each one a little endian address
+pub const NMI_VECTOR: u16 = 0xFFFA;
+pub const RESET_VECTOR: u16 = 0xFFFC;
+pub const IRQ_VECTOR: u16 = 0xFFFE;
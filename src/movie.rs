@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+// Controller inputs of a run, frame by frame from the power on
+// Replaying them on the same ROM reproduces the run exactly since the
+// emulation has no source of randomness
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Movie {
+    frames: Vec<MovieFrame>,
+}
+
+// Buttons held on both controllers during a frame, one bit per button
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MovieFrame {
+    pub frame: u64,
+    pub buttons: [u8; 2],
+}
+
+impl Movie {
+    pub fn new() -> Movie {
+        Movie::default()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, bincode::Error> {
+        bincode::deserialize(data)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Could not serialize the movie")
+    }
+
+    pub fn push(&mut self, buttons: [u8; 2]) {
+        let frame: u64 = self.frames.len() as u64;
+        self.frames.push(MovieFrame { frame, buttons });
+    }
+
+    pub fn get(&self, frame: u64) -> Option<&MovieFrame> {
+        self.frames.get(frame as usize)
+    }
+
+    pub fn frames(&self) -> &[MovieFrame] {
+        &self.frames
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+// What the NES does with the controller inputs at the start of each frame
+#[derive(Debug, Default)]
+pub enum MovieMode {
+    #[default]
+    Off,
+    Recording(Movie),
+    // the movie and the index of the next frame to play
+    Playing(Movie, u64),
+}
@@ -1,27 +1,1025 @@
-use crate::{cpu::CPU, ram::RAM};
+use std::{
+    fmt, io, mem,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+#[cfg(feature = "screenshot")]
+use std::{fs::File, io::BufWriter};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "apu")]
+use crate::apu::{APU, DEFAULT_SAMPLE_RATE};
+#[cfg(feature = "audio")]
+use crate::audio::Audio;
+#[cfg(feature = "gamepad")]
+use crate::gamepad::Gamepads;
+#[cfg(feature = "screenshot")]
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::{
+    cartridge::{Header, Region, RomError},
+    controller::{Controller, ControllerState},
+    cpu::{CpuError, CPU},
+    mem::PRG_ROM_START,
+    movie::{Movie, MovieMode},
+    ppu::PPU,
+    ram::{PowerOnRam, RAM},
+    rewind::RewindBuffer,
+};
+
+const PPU_DOTS_PER_CPU_CYCLE: u64 = 3;
+// CPU cycles halted by the OAM DMA, one more when it starts on an odd cycle
+const OAM_DMA_CYCLES: u64 = 513;
+
+// blargg's test ROMs report through PRG RAM: a status byte at 0x6000, the
+// 0xDE 0xB0 0x61 signature at 0x6001 - 0x6003 once the status is valid, and a
+// NUL-terminated message from 0x6004
+const TEST_STATUS_ADDRESS: u16 = 0x6000;
+const TEST_SIGNATURE_ADDRESS: u16 = 0x6001;
+const TEST_SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const TEST_MESSAGE_ADDRESS: u16 = 0x6004;
+// status values other than the result code
+const TEST_RUNNING: u8 = 0x80;
+const TEST_NEEDS_RESET: u8 = 0x81;
+// the ROM asks for the reset button to be pressed at least 100ms later
+const TEST_RESET_DELAY_FRAMES: u64 = 7;
+
+// Outcome of a blargg test ROM, see NES::run_until_test_result
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResult {
+    // 0 when all the tests passed, otherwise the number of the failed one
+    pub code: u8,
+    pub message: String,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.code == 0
+    }
+}
 
 #[derive(Debug)]
 pub struct NES {
     cpu: CPU,
     ram: RAM,
+    region: Region,
+    // emulation speed relative to the real console, f64::INFINITY for uncapped
+    speed: f64,
+    // cycles the last instruction of the previous frame ran past its end
+    surplus_cycles: u64,
+    movie: MovieMode,
+    rewind: Option<RewindBuffer>,
+    // nothing advances while paused, see NES::pause
+    paused: bool,
+    on_frame: Option<FrameCallback>,
+    #[cfg(feature = "audio")]
+    audio: Option<Audio>,
+    #[cfg(feature = "gamepad")]
+    gamepads: Option<Gamepads>,
+}
+
+// Called with the frame buffer every time the PPU completes a frame, see NES::on_frame
+type FrameHandler = dyn FnMut(&[u8]);
+
+struct FrameCallback(Box<FrameHandler>);
+
+impl fmt::Debug for FrameCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FrameCallback").finish_non_exhaustive()
+    }
 }
 
-const CYCLES_PER_FRAME: u64 = 29781;
+#[derive(Serialize)]
+struct SaveState<'a> {
+    cpu: &'a CPU,
+    ram: &'a RAM,
+    // the next frame is shortened by it, see NES::run_frame
+    surplus_cycles: u64,
+}
+
+#[derive(Deserialize)]
+struct LoadedState {
+    cpu: CPU,
+    ram: RAM,
+    surplus_cycles: u64,
+}
+
+// Reads the ROM metadata from its header only, without loading PRG or CHR data
+pub fn inspect_header(data: &[u8]) -> Result<Header, RomError> {
+    Header::from_rom(data)
+}
+
+#[derive(Debug, Clone)]
+enum RomSource {
+    File(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+// Options of a NES, set before powering it on:
+// NesBuilder::from_file("game.nes").region(Region::Pal).trace(true).build()
+#[derive(Debug, Clone)]
+pub struct NesBuilder {
+    rom: RomSource,
+    // taken from the ROM header if not set
+    region: Option<Region>,
+    trace: bool,
+    // overrides the reset vector, e.g. 0xC000 to run nestest automatically
+    start_pc: Option<u16>,
+    power_on_ram: PowerOnRam,
+    #[cfg(feature = "apu")]
+    sample_rate: f64,
+}
+
+impl NesBuilder {
+    // The battery-backed PRG RAM is persisted next to the ROM file
+    pub fn from_file<P: AsRef<Path>>(rom_file: P) -> NesBuilder {
+        NesBuilder::with_rom(RomSource::File(rom_file.as_ref().to_path_buf()))
+    }
+
+    pub fn from_bytes(data: &[u8]) -> NesBuilder {
+        NesBuilder::with_rom(RomSource::Bytes(data.to_vec()))
+    }
+
+    fn with_rom(rom: RomSource) -> NesBuilder {
+        NesBuilder {
+            rom,
+            region: None,
+            trace: false,
+            start_pc: None,
+            power_on_ram: PowerOnRam::Zero,
+            #[cfg(feature = "apu")]
+            sample_rate: DEFAULT_SAMPLE_RATE,
+        }
+    }
+
+    pub fn region(mut self, region: Region) -> NesBuilder {
+        self.region = Some(region);
+        self
+    }
+
+    // Prints every instruction executed, off by default
+    pub fn trace(mut self, trace: bool) -> NesBuilder {
+        self.trace = trace;
+        self
+    }
+
+    pub fn start_pc(mut self, pc: u16) -> NesBuilder {
+        self.start_pc = Some(pc);
+        self
+    }
+
+    // Zeroed by default, for reproducible runs
+    pub fn power_on_ram(mut self, power_on_ram: PowerOnRam) -> NesBuilder {
+        self.power_on_ram = power_on_ram;
+        self
+    }
+
+    #[cfg(feature = "apu")]
+    pub fn sample_rate(mut self, sample_rate: f64) -> NesBuilder {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    pub fn build(self) -> Result<NES, RomError> {
+        let mut ram: RAM = match &self.rom {
+            RomSource::File(path) => RAM::from_file(path)?,
+            RomSource::Bytes(data) => RAM::from_bytes(data)?,
+        };
+        ram.fill_work_ram(self.power_on_ram);
+        let region: Region = self.region.unwrap_or(ram.cartridge().header().region);
+        let mut nes: NES = NES::with_ram(ram, region);
+        nes.cpu.set_trace(self.trace);
+        if let Some(pc) = self.start_pc {
+            nes.cpu.set_pc(pc);
+        }
+        #[cfg(feature = "apu")]
+        nes.set_sample_rate(self.sample_rate);
+        Ok(nes)
+    }
+}
 
 impl NES {
-    pub fn new(rom_file: &str) -> NES {
-        let ram: RAM = RAM::from_file(rom_file);
-        NES {
-            cpu: CPU::from_ram(&ram),
+    // Panics if the ROM cannot be loaded, NesBuilder reports the error instead
+    pub fn new(rom_file: &str, region: Region) -> NES {
+        NesBuilder::from_file(rom_file)
+            .region(region)
+            .build()
+            .unwrap_or_else(|err| panic!("Could not load {}: {}", rom_file, err))
+    }
+
+    // The region is taken from the ROM header
+    pub fn from_bytes(data: &[u8]) -> Result<NES, RomError> {
+        NesBuilder::from_bytes(data).build()
+    }
+
+    fn with_ram(mut ram: RAM, region: Region) -> NES {
+        #[cfg_attr(not(feature = "apu"), allow(unused_mut))]
+        let mut nes: NES = NES {
+            cpu: CPU::from_bus(&mut ram),
             ram,
-        }
+            region,
+            speed: 1.0,
+            surplus_cycles: 0,
+            movie: MovieMode::Off,
+            rewind: None,
+            paused: false,
+            on_frame: None,
+            #[cfg(feature = "audio")]
+            audio: None,
+            #[cfg(feature = "gamepad")]
+            gamepads: None,
+        };
+        #[cfg(feature = "apu")]
+        nes.set_sample_rate(DEFAULT_SAMPLE_RATE);
+        nes
     }
 
-    pub fn run(&mut self) {
-        let mut n_cycles: u64 = CYCLES_PER_FRAME;
+    // Runs until the CPU stops on an error
+    pub fn run(&mut self) -> Result<(), CpuError> {
+        let mut next_frame: Instant = Instant::now();
         loop {
-            n_cycles = self.cpu.execute_instructions(&mut self.ram, n_cycles) % CYCLES_PER_FRAME;
+            #[cfg(feature = "gamepad")]
+            if let Some(gamepads) = &mut self.gamepads {
+                gamepads.update();
+                for port in 0..2 {
+                    self.ram
+                        .controller_mut(port)
+                        .set_state(gamepads.controller_state(port));
+                }
+            }
+            self.run_frame()?;
             // TODO render
+            #[cfg(feature = "audio")]
+            if let Some(audio) = &self.audio {
+                audio.queue_samples(self.ram.apu_mut().drain_samples());
+            }
+            self.throttle(&mut next_frame);
+        }
+    }
+
+    // Runs exactly n frames as fast as possible, for headless use such as test ROMs
+    pub fn run_frames(&mut self, n: u64) -> Result<(), CpuError> {
+        for _ in 0..n {
+            self.run_frame()?;
+        }
+        Ok(())
+    }
+
+    // The cycles the previous frame ran over are taken from this one
+    fn run_frame(&mut self) -> Result<(), CpuError> {
+        if self.paused {
+            return Ok(());
+        }
+        self.update_movie();
+        let n_cycles: u64 = self
+            .region
+            .cycles_per_frame()
+            .saturating_sub(self.surplus_cycles);
+        let mut cycles: u64 = 0;
+        while cycles < n_cycles {
+            cycles += self.step()?;
+        }
+        self.surplus_cycles = cycles - n_cycles;
+        if self.rewind.as_mut().is_some_and(RewindBuffer::frame_done) {
+            let state: Vec<u8> = self.save_state();
+            if let Some(rewind) = &mut self.rewind {
+                rewind.push(state);
+            }
+        }
+        Ok(())
+    }
+
+    // Feeds the controllers from the movie being played, or logs them in the one being recorded
+    fn update_movie(&mut self) {
+        match &mut self.movie {
+            MovieMode::Off => {}
+            MovieMode::Recording(movie) => movie.push([
+                self.ram.controller(0).buttons(),
+                self.ram.controller(1).buttons(),
+            ]),
+            MovieMode::Playing(movie, next_frame) => match movie.get(*next_frame) {
+                Some(frame) => {
+                    let buttons: [u8; 2] = frame.buttons;
+                    *next_frame += 1;
+                    self.ram.controller_mut(0).set_buttons(buttons[0]);
+                    self.ram.controller_mut(1).set_buttons(buttons[1]);
+                }
+                // the controllers keep their last state once the movie is over
+                None => self.movie = MovieMode::Off,
+            },
+        }
+    }
+
+    // Records the controller inputs of every frame from now on; starting right
+    // after power on gives a movie that can be replayed from a fresh NES
+    pub fn start_recording(&mut self) {
+        self.movie = MovieMode::Recording(Movie::new());
+    }
+
+    // Returns the recorded movie, if a recording was running
+    pub fn stop_recording(&mut self) -> Option<Movie> {
+        match mem::take(&mut self.movie) {
+            MovieMode::Recording(movie) => Some(movie),
+            other => {
+                self.movie = other;
+                None
+            }
+        }
+    }
+
+    // The movie overrides the controller inputs until its last frame
+    pub fn play_movie(&mut self, movie: Movie) {
+        self.movie = MovieMode::Playing(movie, 0);
+    }
+
+    pub fn movie_mode(&self) -> &MovieMode {
+        &self.movie
+    }
+
+    // Snapshots the state every interval frames, keeping the last capacity ones
+    pub fn enable_rewind(&mut self, interval: u64, capacity: usize) {
+        self.rewind = Some(RewindBuffer::new(interval, capacity));
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    pub fn rewind_buffer(&self) -> Option<&RewindBuffer> {
+        self.rewind.as_ref()
+    }
+
+    // Goes back to the most recent snapshot and drops it, so calling it again
+    // goes further back; returns false if there is nothing to rewind to
+    pub fn rewind(&mut self) -> Result<bool, bincode::Error> {
+        match self.rewind.as_mut().and_then(RewindBuffer::pop) {
+            Some(state) => {
+                self.load_state(&state)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // Freezes the machine, e.g. while a frontend menu is open: the run and
+    // step functions keep returning without advancing the CPU, the PPU or the
+    // APU, and no samples are produced so the audio output holds still
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // 2.0 runs twice as fast as the console, f64::INFINITY as fast as possible
+    pub fn set_speed(&mut self, multiplier: f64) {
+        assert!(multiplier > 0.0, "Speed must be positive");
+        self.speed = multiplier;
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    // Sleeps until the time the next frame is due, given the time the current one took
+    fn throttle(&self, next_frame: &mut Instant) {
+        let frame_duration: Duration =
+            Duration::from_secs_f64(1.0 / (self.region.frames_per_second() * self.speed));
+        *next_frame += frame_duration;
+        let now: Instant = Instant::now();
+        if *next_frame > now {
+            thread::sleep(*next_frame - now);
+        } else {
+            // running late, don't try to catch up on the missed frames
+            *next_frame = now;
+        }
+    }
+
+    // Calls callback with the RGBA frame buffer once per frame completed by
+    // the PPU, in place of polling it; nothing is called while paused
+    pub fn on_frame(&mut self, callback: impl FnMut(&[u8]) + 'static) {
+        self.on_frame = Some(FrameCallback(Box::new(callback)));
+    }
+
+    pub fn clear_on_frame(&mut self) {
+        self.on_frame = None;
+    }
+
+    // Executes a single instruction, then services the pending interrupt if
+    // any, and returns the number of cycles it took
+    pub fn step(&mut self) -> Result<u64, CpuError> {
+        if self.paused {
+            return Ok(0);
+        }
+        let frame_count: u64 = self.ram.ppu().frame_count();
+        let mut cycles: u64 = self.cpu.step(&mut self.ram)?;
+        // the copy itself is done at once, the PPU and the APU then catch up
+        // with the cycles the CPU was halted
+        if self.ram.take_oam_dma() {
+            let stall: u64 = OAM_DMA_CYCLES + self.cpu.cycles() % 2;
+            self.cpu.stall(stall);
+            cycles += stall;
+        }
+        self.tick(cycles);
+        // interrupts are checked between instructions, the NMI first; the IRQ
+        // line is level-triggered while the NMI is raised on an edge
+        let interrupt_cycles: u64 = if self.ram.ppu_mut().take_nmi() {
+            self.cpu.nmi(&mut self.ram)
+        } else if self.ram.irq() {
+            self.cpu.irq(&mut self.ram)
+        } else {
+            0
+        };
+        self.tick(interrupt_cycles);
+        if self.ram.ppu().frame_count() != frame_count {
+            if let Some(on_frame) = &mut self.on_frame {
+                (on_frame.0)(self.ram.ppu().frame_buffer());
+            }
+        }
+        Ok(cycles + interrupt_cycles)
+    }
+
+    // Runs until the PPU starts the next scanline and returns the number of
+    // cycles it took; the instruction that crosses the boundary is completed
+    pub fn step_scanline(&mut self) -> Result<u64, CpuError> {
+        if self.paused {
+            return Ok(0);
+        }
+        let scanline: u16 = self.ppu().scanline();
+        let mut cycles: u64 = 0;
+        while self.ppu().scanline() == scanline {
+            cycles += self.step()?;
+        }
+        Ok(cycles)
+    }
+
+    // Runs until the PPU starts the next frame, at the first dots of scanline 0,
+    // and returns the number of cycles it took
+    pub fn step_frame(&mut self) -> Result<u64, CpuError> {
+        if self.paused {
+            return Ok(0);
+        }
+        let frame_count: u64 = self.ppu().frame_count();
+        let mut cycles: u64 = 0;
+        while self.ppu().frame_count() == frame_count {
+            cycles += self.step()?;
+        }
+        Ok(cycles)
+    }
+
+    // Catches the PPU and the APU up with the CPU, the PPU running 3 dots per
+    // CPU cycle
+    // TODO PAL PPUs run 3.2 dots per CPU cycle
+    fn tick(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            for _ in 0..PPU_DOTS_PER_CPU_CYCLE {
+                self.ram.tick_ppu();
+            }
+            #[cfg(feature = "apu")]
+            self.ram.apu_mut().tick();
+        }
+    }
+
+    // Number of audio samples per second produced by the APU
+    #[cfg(feature = "apu")]
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        let region: Region = self.region;
+        self.ram.apu_mut().set_sample_rate(sample_rate, region);
+    }
+
+    // Plays the sound on the given output while running
+    #[cfg(feature = "audio")]
+    pub fn set_audio(&mut self, audio: Audio) {
+        self.set_sample_rate(audio.sample_rate() as f64);
+        self.audio = Some(audio);
+    }
+
+    // Feeds the controllers from the gamepads while running
+    #[cfg(feature = "gamepad")]
+    pub fn set_gamepads(&mut self, gamepads: Gamepads) {
+        self.gamepads = Some(gamepads);
+    }
+
+    // Runs a blargg test ROM until it reports its result, pressing reset when
+    // it asks for it; returns None if there is no result after max_frames
+    pub fn run_until_test_result(
+        &mut self,
+        max_frames: u64,
+    ) -> Result<Option<TestResult>, CpuError> {
+        let mut frame: u64 = 0;
+        while frame < max_frames {
+            self.run_frames(1)?;
+            frame += 1;
+            let ram: &RAM = &self.ram;
+            if ram.dump(TEST_SIGNATURE_ADDRESS, TEST_SIGNATURE.len()) != TEST_SIGNATURE {
+                continue;
+            }
+            match ram.peek(TEST_STATUS_ADDRESS) {
+                TEST_RUNNING => {}
+                TEST_NEEDS_RESET => {
+                    self.run_frames(TEST_RESET_DELAY_FRAMES)?;
+                    frame += TEST_RESET_DELAY_FRAMES;
+                    self.reset();
+                }
+                code => {
+                    let message: Vec<u8> = (TEST_MESSAGE_ADDRESS..PRG_ROM_START)
+                        .map(|addr| ram.peek(addr))
+                        .take_while(|&byte| byte != 0)
+                        .collect();
+                    return Ok(Some(TestResult {
+                        code,
+                        message: String::from_utf8_lossy(&message).into_owned(),
+                    }));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // Snapshot of the whole machine state, without the ROM data
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::new();
+        self.save_state_to(&mut data)
+            .expect("Could not serialize the machine state");
+        data
+    }
+
+    // Same as save_state, written straight to writer, e.g. a file
+    pub fn save_state_to(&self, writer: &mut dyn io::Write) -> Result<(), bincode::Error> {
+        let state: SaveState = SaveState {
+            cpu: &self.cpu,
+            ram: &self.ram,
+            surplus_cycles: self.surplus_cycles,
+        };
+        bincode::serialize_into(writer, &state)
+    }
+
+    // Restores a snapshot taken with save_state while the same ROM was loaded
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), bincode::Error> {
+        self.load_state_from(&mut &data[..])
+    }
+
+    // Same as load_state, read from reader up to the end of the snapshot
+    pub fn load_state_from(&mut self, reader: &mut dyn io::Read) -> Result<(), bincode::Error> {
+        let mut state: LoadedState = bincode::deserialize_from(reader)?;
+        state.ram.restore(&mut self.ram);
+        state.cpu.set_trace(self.cpu.trace());
+        self.cpu = state.cpu;
+        self.ram = state.ram;
+        self.surplus_cycles = state.surplus_cycles;
+        Ok(())
+    }
+
+    // Same as pressing the reset button: the ROM, work RAM and PRG RAM are kept
+    pub fn reset(&mut self) {
+        self.cpu.reset(&mut self.ram);
+        self.ram.ppu_mut().reset();
+        #[cfg(feature = "apu")]
+        self.ram.apu_mut().reset();
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn header(&self) -> &Header {
+        self.ram.cartridge().header()
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
+    pub fn ram(&self) -> &RAM {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut RAM {
+        &mut self.ram
+    }
+
+    #[cfg(feature = "apu")]
+    pub fn apu(&self) -> &APU {
+        self.ram.apu()
+    }
+
+    #[cfg(feature = "apu")]
+    pub fn apu_mut(&mut self) -> &mut APU {
+        self.ram.apu_mut()
+    }
+
+    // port is 0 or 1, panics otherwise
+    pub fn controller_mut(&mut self, port: u8) -> &mut Controller {
+        self.ram.controller_mut(port)
+    }
+
+    // port is 0 or 1, panics otherwise
+    pub fn set_controller(&mut self, port: u8, state: ControllerState) {
+        self.ram.controller_mut(port).set_state(state);
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        self.ram.ppu()
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut PPU {
+        self.ram.ppu_mut()
+    }
+
+    // Writes the current frame buffer to a PNG file
+    #[cfg(feature = "screenshot")]
+    pub fn save_screenshot<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file: File = File::create(path)?;
+        let mut encoder: png::Encoder<BufWriter<File>> = png::Encoder::new(
+            BufWriter::new(file),
+            SCREEN_WIDTH as u32,
+            SCREEN_HEIGHT as u32,
+        );
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer: png::Writer<BufWriter<File>> = encoder.write_header()?;
+        writer.write_image_data(self.ppu().frame_buffer())?;
+        Ok(())
+    }
+
+    // Writes the battery-backed PRG RAM to the .sav file next to the ROM
+    pub fn save(&self) -> io::Result<()> {
+        self.ram.cartridge().save()
+    }
+}
+
+impl Drop for NES {
+    fn drop(&mut self) {
+        if let Err(err) = self.save() {
+            eprintln!("Could not write save file: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "screenshot")]
+    use std::fs;
+    use std::{env, process::Command};
+
+    use super::*;
+    use crate::{
+        cartridge::{Mirroring, HEADER_SIZE},
+        cpu::StatusFlag,
+        test_rom,
+    };
+
+    // NES running code from 0x8000 on an NROM cartridge
+    fn nes_with_code(code: &[u8]) -> NES {
+        NES::from_bytes(&test_rom::nrom(code)).unwrap()
+    }
+
+    #[test]
+    fn inspect_header_reads_a_header_slice_without_the_rom_body() {
+        let header: Header = inspect_header(&test_rom::header(2, 1, 0x41)).unwrap();
+        assert_eq!(header.mapper, 4);
+        assert_eq!((header.prg_rom_size, header.chr_rom_size), (0x8000, 0x2000));
+        assert_eq!(header.mirroring, Mirroring::Vertical);
+        assert_eq!(header.region, Region::Ntsc);
+    }
+
+    #[test]
+    fn inspect_header_reports_invalid_headers() {
+        let mut header: [u8; HEADER_SIZE] = test_rom::header(0, 0, 0);
+        // NES 2.0 exponent PRG ROM size of 2^63 * 3
+        header[4] = 0xFD;
+        header[7] = 0x08;
+        header[9] = 0x0F;
+        assert!(matches!(
+            inspect_header(&header),
+            Err(RomError::InvalidSize)
+        ));
+        assert!(matches!(
+            inspect_header(&header[..8]),
+            Err(RomError::Truncated {
+                expected: HEADER_SIZE,
+                actual: 8
+            })
+        ));
+    }
+
+    #[test]
+    fn from_bytes_reports_hostile_headers() {
+        // NES 2.0 exponent sizes of 2^62 * 3 for both PRG and CHR ROM
+        let mut rom: [u8; HEADER_SIZE] = test_rom::header(0xF9, 0xF9, 0);
+        rom[7] = 0x08;
+        rom[9] = 0xFF;
+        assert!(matches!(NES::from_bytes(&rom), Err(RomError::InvalidSize)));
+        // 2 pages of PRG ROM declared, 1 present
+        let mut rom: Vec<u8> = test_rom::header(2, 0, 0).to_vec();
+        rom.extend([0; 0x4000]);
+        assert!(matches!(
+            NES::from_bytes(&rom),
+            Err(RomError::Truncated {
+                expected: 0x8010,
+                actual: 0x4010
+            })
+        ));
+    }
+
+    // Set when the test below runs itself in a child process
+    const NO_OUTPUT_CHILD: &str = "NES_EMULATOR_NO_OUTPUT_CHILD";
+    const OUTPUT_START: &str = "<output>";
+    const OUTPUT_END: &str = "</output>";
+
+    #[test]
+    fn default_nes_prints_nothing_while_running() {
+        if env::var_os(NO_OUTPUT_CHILD).is_some() {
+            print!("{}", OUTPUT_START);
+            let mut nes: NES = nes_with_code(&[0xE6, 0x10, 0x4C, 0x00, 0x80]);
+            nes.step().unwrap();
+            nes.run_frames(1).unwrap();
+            print!("{}", OUTPUT_END);
+            return;
+        }
+        // the test harness captures stdout, the child prints it as is
+        let output = Command::new(env::current_exe().unwrap())
+            .args([
+                "nes::tests::default_nes_prints_nothing_while_running",
+                "--exact",
+                "--nocapture",
+                "--test-threads=1",
+            ])
+            .env(NO_OUTPUT_CHILD, "1")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout: String = String::from_utf8_lossy(&output.stdout).into_owned();
+        let start: usize = stdout.find(OUTPUT_START).unwrap() + OUTPUT_START.len();
+        let end: usize = stdout.find(OUTPUT_END).unwrap();
+        let printed: &str = &stdout[start..end];
+        assert!(printed.is_empty(), "printed {} bytes", printed.len());
+    }
+
+    #[test]
+    fn reset_reloads_pc_from_the_vector_and_keeps_work_ram() {
+        // LDA #$5A, STA $0123, LDX #$00, TXS
+        let mut nes: NES = nes_with_code(&[0xA9, 0x5A, 0x8D, 0x23, 0x01, 0xA2, 0x00, 0x9A]);
+        for _ in 0..4 {
+            nes.step().unwrap();
+        }
+        assert_eq!((nes.cpu().pc(), nes.cpu().sp()), (0x8008, 0x00));
+        nes.reset();
+        assert_eq!((nes.cpu().pc(), nes.cpu().sp()), (0x8000, 0xFD));
+        assert!(nes.cpu().flag(StatusFlag::InterruptDisable));
+        assert_eq!(nes.ram().work_ram()[0x0123], 0x5A);
+    }
+
+    #[test]
+    fn oam_dma_copies_a_page_to_oam_and_halts_the_cpu() {
+        // LDA #$02, STA $4014, BIT $00, STA $4014
+        let mut nes: NES =
+            nes_with_code(&[0xA9, 0x02, 0x8D, 0x14, 0x40, 0x24, 0x00, 0x8D, 0x14, 0x40]);
+        for (i, byte) in nes.ram_mut().work_ram_mut()[0x200..0x300]
+            .iter_mut()
+            .enumerate()
+        {
+            *byte = i as u8;
+        }
+        nes.step().unwrap();
+        // 7 cycles of reset and 6 of instructions
+        assert_eq!(nes.step().unwrap(), 4 + 514);
+        assert_eq!(nes.cpu().cycles(), 13 + 514);
+        nes.step().unwrap();
+        assert_eq!(nes.step().unwrap(), 4 + 513);
+        assert!(nes
+            .ppu()
+            .oam()
+            .iter()
+            .enumerate()
+            .all(|(i, &byte)| byte == i as u8));
+    }
+
+    #[test]
+    fn test_rom_protocol_is_driven_through_a_reset_to_the_result() {
+        #[rustfmt::skip]
+        let code: [u8; 51] = [
+            // LDA $6000, CMP #$81, BEQ pass
+            0xAD, 0x00, 0x60, 0xC9, 0x81, 0xF0, 0x17,
+            // signature, then the reset request
+            0xA9, 0xDE, 0x8D, 0x01, 0x60, 0xA9, 0xB0, 0x8D, 0x02, 0x60,
+            0xA9, 0x61, 0x8D, 0x03, 0x60, 0xA9, 0x81, 0x8D, 0x00, 0x60,
+            0x4C, 0x1B, 0x80,
+            // pass: "OK", then the result code 0
+            0xA9, 0x4F, 0x8D, 0x04, 0x60, 0xA9, 0x4B, 0x8D, 0x05, 0x60,
+            0xA9, 0x00, 0x8D, 0x06, 0x60, 0x8D, 0x00, 0x60,
+            0x4C, 0x30, 0x80,
+        ];
+        let mut nes: NES = nes_with_code(&code);
+        let result: TestResult = nes.run_until_test_result(20).unwrap().unwrap();
+        assert!(result.passed());
+        assert_eq!(result.message, "OK");
+        assert!(nes.ppu().frame_count() > TEST_RESET_DELAY_FRAMES);
+    }
+
+    #[test]
+    fn running_two_frames_is_deterministic() {
+        // LDA #$1E, STA $2001, then INC $10 in a loop
+        let code: [u8; 10] = [0xA9, 0x1E, 0x8D, 0x01, 0x20, 0xE6, 0x10, 0x4C, 0x05, 0x80];
+        let run = || {
+            let mut nes: NES = nes_with_code(&code);
+            nes.run_frames(2).unwrap();
+            nes
+        };
+        let (first, second) = (run(), run());
+        let cycles: u64 = first.cpu().cycles();
+        assert!(cycles >= 2 * first.region().cycles_per_frame());
+        assert_eq!(cycles, second.cpu().cycles());
+        assert_ne!(first.ram().work_ram()[0x10], 0);
+        assert_eq!(first.ram().work_ram(), second.ram().work_ram());
+        assert_eq!(first.ppu().frame_buffer(), second.ppu().frame_buffer());
+        assert_eq!(first.save_state(), second.save_state());
+    }
+
+    #[test]
+    fn frames_carry_the_cycles_run_over_to_the_next_one() {
+        // INC $10, NOP and JMP, 10 cycles per loop
+        let mut nes: NES = nes_with_code(&[0xE6, 0x10, 0xEA, 0x4C, 0x00, 0x80]);
+        let cycles_per_frame: u64 = nes.region().cycles_per_frame();
+        let start: u64 = nes.cpu().cycles();
+        let mut over: Vec<u64> = Vec::new();
+        for frame in 1..=2 {
+            nes.run_frames(1).unwrap();
+            over.push(nes.cpu().cycles() - start - frame * cycles_per_frame);
+        }
+        // the first frame ends in the middle of a loop, the second one is
+        // shortened accordingly so the total stays within the 5 cycles of INC
+        assert!(over[0] > 0);
+        assert!(over.iter().all(|&cycles| cycles < 5));
+    }
+
+    #[test]
+    fn recorded_movie_replays_to_the_same_state() {
+        // strobes controller 1 and adds its A button to 0x0010, in a loop
+        #[rustfmt::skip]
+        let code: [u8; 23] = [
+            0xA9, 0x01, 0x8D, 0x16, 0x40, 0xA9, 0x00, 0x8D, 0x16, 0x40,
+            0xAD, 0x16, 0x40, 0x29, 0x01, 0x18, 0x65, 0x10, 0x85, 0x10,
+            0x4C, 0x00, 0x80,
+        ];
+        let mut nes: NES = nes_with_code(&code);
+        nes.start_recording();
+        for buttons in [1, 0, 1, 1, 0] {
+            nes.controller_mut(0).set_buttons(buttons);
+            nes.run_frames(1).unwrap();
+        }
+        let movie: Movie = nes.stop_recording().unwrap();
+        assert_eq!(movie.len(), 5);
+        let movie: Movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+
+        let mut replay: NES = nes_with_code(&code);
+        replay.play_movie(movie);
+        replay.run_frames(5).unwrap();
+        assert_ne!(nes.ram().work_ram()[0x10], 0);
+        assert_eq!(replay.ram().work_ram(), nes.ram().work_ram());
+        assert_eq!(replay.cpu().cycles(), nes.cpu().cycles());
+        // playback stops after the last frame of the movie
+        replay.run_frames(1).unwrap();
+        assert!(matches!(replay.movie_mode(), MovieMode::Off));
+    }
+
+    #[test]
+    fn streamed_and_vec_save_states_are_identical() {
+        // INC $10 in a loop
+        let mut nes: NES = nes_with_code(&[0xE6, 0x10, 0x4C, 0x00, 0x80]);
+        nes.run_frames(1).unwrap();
+        let mut streamed: Vec<u8> = Vec::new();
+        nes.save_state_to(&mut streamed).unwrap();
+        let state: Vec<u8> = nes.save_state();
+        assert_eq!(streamed, state);
+
+        nes.run_frames(1).unwrap();
+        // the reader is left after the end of the snapshot
+        streamed.push(0xAA);
+        let mut reader: &[u8] = &streamed;
+        nes.load_state_from(&mut reader).unwrap();
+        assert_eq!(reader, [0xAA]);
+        assert_eq!(nes.save_state(), state);
+    }
+
+    #[test]
+    fn loaded_state_runs_the_same_as_the_original() {
+        // INC $10, NOP and JMP, so that frames end in the middle of a loop
+        let code: [u8; 6] = [0xE6, 0x10, 0xEA, 0x4C, 0x00, 0x80];
+        let mut nes: NES = nes_with_code(&code);
+        nes.run_frames(1).unwrap();
+        let state: Vec<u8> = nes.save_state();
+        let mut loaded: NES = nes_with_code(&code);
+        loaded.load_state(&state).unwrap();
+        nes.run_frames(2).unwrap();
+        loaded.run_frames(2).unwrap();
+        assert_eq!(loaded.cpu().cycles(), nes.cpu().cycles());
+        assert_eq!(loaded.save_state(), nes.save_state());
+        assert_eq!(loaded.ppu().frame_buffer(), nes.ppu().frame_buffer());
+    }
+
+    #[test]
+    fn rewind_restores_the_previous_snapshots() {
+        // INC $10 in a loop
+        let mut nes: NES = nes_with_code(&[0xE6, 0x10, 0x4C, 0x00, 0x80]);
+        nes.enable_rewind(1, 4);
+        nes.run_frames(1).unwrap();
+        let first: Vec<u8> = nes.save_state();
+        nes.run_frames(1).unwrap();
+        let second: Vec<u8> = nes.save_state();
+        nes.run_frames(1).unwrap();
+        assert_ne!(first, second);
+        assert!(nes.rewind().unwrap());
+        assert!(nes.rewind().unwrap());
+        assert_eq!(nes.save_state(), second);
+        assert!(nes.rewind().unwrap());
+        assert_eq!(nes.save_state(), first);
+        assert!(!nes.rewind().unwrap());
+    }
+
+    // Channel difference allowed between a screenshot and its reference
+    #[cfg(feature = "screenshot")]
+    const SCREENSHOT_TOLERANCE: u8 = 8;
+
+    #[cfg(feature = "screenshot")]
+    fn read_png(path: &Path) -> Vec<u8> {
+        let decoder: png::Decoder<File> = png::Decoder::new(File::open(path).unwrap());
+        let mut reader: png::Reader<File> = decoder.read_info().unwrap();
+        let mut data: Vec<u8> = vec![0; reader.output_buffer_size()];
+        let info: png::OutputInfo = reader.next_frame(&mut data).unwrap();
+        assert_eq!(
+            (info.width, info.height),
+            (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+        );
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        data
+    }
+
+    #[cfg(feature = "screenshot")]
+    #[test]
+    fn screenshot_matches_the_reference_image() {
+        #[rustfmt::skip]
+        let code: [u8; 72] = [
+            // backdrop 0x21 and color 1 of palette 0 0x16
+            0xA9, 0x3F, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20,
+            0xA9, 0x21, 0x8D, 0x07, 0x20, 0xA9, 0x16, 0x8D, 0x07, 0x20,
+            // tile 0, used by the whole nametable, is a 4x4 checkerboard
+            0xA9, 0x00, 0x8D, 0x06, 0x20, 0x8D, 0x06, 0x20,
+            0xA9, 0xF0, 0x8D, 0x07, 0x20, 0x8D, 0x07, 0x20, 0x8D, 0x07, 0x20, 0x8D, 0x07, 0x20,
+            0xA9, 0x0F, 0x8D, 0x07, 0x20, 0x8D, 0x07, 0x20, 0x8D, 0x07, 0x20, 0x8D, 0x07, 0x20,
+            // v back to 0x0000, then the background is shown
+            0xA9, 0x00, 0x8D, 0x06, 0x20, 0x8D, 0x06, 0x20,
+            0xA9, 0x0A, 0x8D, 0x01, 0x20,
+            0x4C, 0x45, 0x80,
+        ];
+        let mut nes: NES = nes_with_code(&code);
+        nes.run_frames(2).unwrap();
+        let path: PathBuf = env::temp_dir().join(format!(
+            "nes_emulator_screenshot_{}.png",
+            std::process::id()
+        ));
+        nes.save_screenshot(&path).unwrap();
+        let screenshot: Vec<u8> = read_png(&path);
+        fs::remove_file(&path).unwrap();
+        let reference: Vec<u8> =
+            read_png(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/checkerboard.png"));
+        assert_eq!(screenshot.len(), reference.len());
+        assert!(screenshot
+            .iter()
+            .zip(&reference)
+            .all(|(a, b)| a.abs_diff(*b) <= SCREENSHOT_TOLERANCE));
+    }
+
+    #[test]
+    fn power_on_ram_fills_the_work_ram() {
+        let rom: Vec<u8> = test_rom::nrom(&[]);
+        let work_ram = |power_on_ram: PowerOnRam| -> Vec<u8> {
+            let nes: NES = NesBuilder::from_bytes(&rom)
+                .power_on_ram(power_on_ram)
+                .build()
+                .unwrap();
+            nes.ram().work_ram().to_vec()
+        };
+        // the same seed gives the same content on every power on
+        let random: Vec<u8> = work_ram(PowerOnRam::Random(42));
+        assert_eq!(random, work_ram(PowerOnRam::Random(42)));
+        assert_ne!(random, work_ram(PowerOnRam::Random(43)));
+        assert!(random.iter().any(|&byte| byte != random[0]));
+
+        let pattern: Vec<u8> = work_ram(PowerOnRam::Pattern);
+        for chunk in pattern.chunks_exact(8) {
+            assert_eq!(chunk, [0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
         }
+        assert!(work_ram(PowerOnRam::Zero).iter().all(|&byte| byte == 0));
     }
 }
@@ -0,0 +1,1078 @@
+// PPU registers, as seen by the CPU at 0x2000 - 0x2007:
+// 0x2000: PPUCTRL
+// 0x2001: PPUMASK
+// 0x2002: PPUSTATUS
+// 0x2003: OAMADDR
+// 0x2004: OAMDATA
+// 0x2005: PPUSCROLL
+// 0x2006: PPUADDR
+// 0x2007: PPUDATA
+
+use std::{cell::Cell, mem};
+
+use serde::{Deserialize, Serialize};
+use serde_big_array::BigArray;
+
+use crate::{
+    cartridge::{Cartridge, Mirroring},
+    mem::{OAMADDR, OAMDATA, PPUADDR, PPUCTRL, PPUDATA, PPUMASK, PPUSCROLL, PPUSTATUS},
+};
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+const DOTS_PER_SCANLINE: u16 = 341;
+const SCANLINES_PER_FRAME: u16 = 262;
+const PRE_RENDER_SCANLINE: u16 = 261;
+const VBLANK_SCANLINE: u16 = 241;
+// dot after which the sprites of the next scanline are evaluated
+const SPRITE_EVALUATION_DOT: u16 = 257;
+const MAX_SPRITES_PER_SCANLINE: usize = 8;
+const NAMETABLE_SIZE: u16 = 0x400;
+const ATTRIBUTE_TABLE_OFFSET: u16 = 0x3C0;
+const SPRITE_PALETTES_OFFSET: u8 = 0x10;
+const PALETTE_START_ADDRESS: u16 = 0x3F00;
+// the PPU address bus is 14 bits wide
+const PPU_ADDRESS_MASK: u16 = 0x3FFF;
+// fields of the v and t scroll registers
+const COARSE_X_MASK: u16 = 0x001F;
+const COARSE_Y_MASK: u16 = 0x03E0;
+const NAMETABLE_MASK: u16 = 0x0C00;
+const FINE_Y_MASK: u16 = 0x7000;
+
+pub type Rgb = [u8; 3];
+
+// 2C02 master palette, indexed by the 6-bit color values stored in palette RAM
+#[rustfmt::skip]
+const MASTER_PALETTE: [Rgb; 64] = [
+    [84, 84, 84], [0, 30, 116], [8, 16, 144], [48, 0, 136],
+    [68, 0, 100], [92, 0, 48], [84, 4, 0], [60, 24, 0],
+    [32, 42, 0], [8, 58, 0], [0, 64, 0], [0, 60, 0],
+    [0, 50, 60], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [152, 150, 152], [8, 76, 196], [48, 50, 236], [92, 30, 228],
+    [136, 20, 176], [160, 20, 100], [152, 34, 32], [120, 60, 0],
+    [84, 90, 0], [40, 114, 0], [8, 124, 0], [0, 118, 40],
+    [0, 102, 120], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [236, 238, 236], [76, 154, 236], [120, 124, 236], [176, 98, 236],
+    [228, 84, 236], [236, 88, 180], [236, 106, 100], [212, 136, 32],
+    [160, 170, 0], [116, 196, 0], [76, 208, 32], [56, 204, 108],
+    [56, 180, 204], [60, 60, 60], [0, 0, 0], [0, 0, 0],
+    [236, 238, 236], [168, 204, 236], [188, 188, 236], [212, 178, 236],
+    [236, 174, 236], [236, 174, 212], [236, 180, 176], [228, 196, 144],
+    [204, 210, 120], [180, 222, 120], [168, 226, 144], [152, 226, 180],
+    [160, 214, 228], [160, 162, 160], [0, 0, 0], [0, 0, 0],
+];
+
+// Palette RAM content at power up, as read on a real console; it is random
+// on some units, games always write it before rendering
+#[rustfmt::skip]
+const POWER_UP_PALETTE: [u8; 0x20] = [
+    0x09, 0x01, 0x00, 0x01, 0x00, 0x02, 0x02, 0x0D, 0x08, 0x10, 0x08, 0x24, 0x00, 0x00, 0x04, 0x2C,
+    0x09, 0x01, 0x34, 0x03, 0x00, 0x04, 0x00, 0x14, 0x08, 0x3A, 0x00, 0x02, 0x00, 0x20, 0x2C, 0x08,
+];
+
+// Attenuation applied by the built-in table to the channels that are not emphasized
+const EMPHASIS_ATTENUATION: f32 = 0.75;
+// PPUMASK bits 5 - 7 emphasize red, green and blue
+const EMPHASIS_SHIFT: u8 = 5;
+
+enum ControlFlag {
+    VramIncrement = 2,
+    SpritePatternTable = 3,
+    BackgroundPatternTable = 4,
+    // 8x16 sprites instead of 8x8
+    SpriteSize = 5,
+    NmiEnabled = 7,
+}
+
+enum MaskFlag {
+    Greyscale = 0,
+    BackgroundLeftColumn = 1,
+    SpritesLeftColumn = 2,
+    BackgroundEnabled = 3,
+    SpritesEnabled = 4,
+}
+
+enum StatusFlag {
+    SpriteOverflow = 5,
+    Sprite0Hit = 6,
+    VBlank = 7,
+}
+
+enum SpriteAttribute {
+    BehindBackground = 5,
+    FlipHorizontally = 6,
+    FlipVertically = 7,
+}
+
+// Maps a base color and the 3 PPUMASK emphasis bits (red, green, blue from
+// the least significant bit) to the RGB color that is output
+#[derive(Debug, Clone)]
+pub struct EmphasisTable {
+    colors: [[Rgb; 64]; 8],
+}
+
+impl EmphasisTable {
+    pub fn from_fn(f: impl Fn(u8, u8) -> Rgb) -> EmphasisTable {
+        let mut colors: [[Rgb; 64]; 8] = [[[0; 3]; 64]; 8];
+        for (emphasis, row) in colors.iter_mut().enumerate() {
+            for (color, rgb) in row.iter_mut().enumerate() {
+                *rgb = f(color as u8, emphasis as u8);
+            }
+        }
+        EmphasisTable { colors }
+    }
+
+    pub fn get(&self, color: u8, emphasis: u8) -> Rgb {
+        self.colors[(emphasis & 0b111) as usize][(color & 0x3F) as usize]
+    }
+
+    // Approximates the hardware by dimming every channel but the emphasized ones
+    fn built_in_color(color: u8, emphasis: u8) -> Rgb {
+        let mut rgb: [f32; 3] = MASTER_PALETTE[(color & 0x3F) as usize].map(|c| c as f32);
+        for channel in 0..3 {
+            if emphasis & (1 << channel) != 0 {
+                for (other, value) in rgb.iter_mut().enumerate() {
+                    if other != channel {
+                        *value *= EMPHASIS_ATTENUATION;
+                    }
+                }
+            }
+        }
+        rgb.map(|c| c as u8)
+    }
+}
+
+impl Default for EmphasisTable {
+    fn default() -> EmphasisTable {
+        EmphasisTable::from_fn(EmphasisTable::built_in_color)
+    }
+}
+
+// Sprite selected during the evaluation of a scanline
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Sprite {
+    index: usize, // position in OAM, sprite 0 being the one used for the hit detection
+    x: u8,
+    row: u8, // row of the tile drawn on the scanline, after vertical flip
+    // pattern table and tile drawn on the scanline, the top or bottom half
+    // of 8x16 sprites
+    table: u16,
+    tile: u8,
+    attributes: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PPU {
+    ctrl: u8, // PPUCTRL
+    mask: u8, // PPUMASK
+    // PPUSTATUS, a Cell since reading it clears VBlank
+    status: Cell<u8>,
+    // 2KB on the console, the last 2KB are only used by four-screen cartridges
+    #[serde(with = "BigArray")]
+    vram: [u8; 4 * NAMETABLE_SIZE as usize],
+    palette_ram: [u8; 0x20],
+    #[serde(with = "BigArray")]
+    oam: [u8; 0x100],
+    // OAMADDR, the byte of OAM accessed through OAMDATA
+    oam_addr: u8,
+    // Internal scroll registers, laid out as 0yyy NNYY YYYX XXXX with y the
+    // fine Y scroll, N the nametable, Y the coarse Y and X the coarse X:
+    // v, the current VRAM address, a Cell since PPUDATA reads move it
+    vram_addr: Cell<u16>,
+    // t, the address of the top left tile of the screen, copied into v
+    // during rendering
+    temp_addr: u16,
+    // x, the fine X scroll
+    fine_x: u8,
+    // w, shared by PPUSCROLL and PPUADDR: false before the first write, true
+    // before the second; PPUSTATUS reads clear it
+    write_toggle: Cell<bool>,
+    // PPUDATA reads return the content of the previous address read
+    read_buffer: Cell<u8>,
+    scanline: u16,
+    dot: u16,
+    // number of frames completed since power up
+    frame_count: u64,
+    // NMI raised and not yet serviced by the CPU
+    nmi_pending: bool,
+    scanline_sprites: Vec<Sprite>,
+    // RGBA pixels of the frame being rendered
+    frame_buffer: Vec<u8>,
+    // part of the frontend configuration rather than of the machine state
+    #[serde(skip)]
+    emphasis_table: EmphasisTable,
+    // debug toggles to draw without a layer, unlike the PPUMASK bits they do
+    // not change what the game sees
+    #[serde(skip)]
+    show_background: bool,
+    #[serde(skip)]
+    show_sprites: bool,
+}
+
+impl PPU {
+    pub fn new() -> PPU {
+        PPU {
+            ctrl: 0,
+            mask: 0,
+            status: Cell::new(0),
+            vram: [0; 4 * NAMETABLE_SIZE as usize],
+            palette_ram: POWER_UP_PALETTE,
+            oam: [0; 0x100],
+            oam_addr: 0,
+            vram_addr: Cell::new(0),
+            temp_addr: 0,
+            fine_x: 0,
+            write_toggle: Cell::new(false),
+            read_buffer: Cell::new(0),
+            scanline: 0,
+            dot: 0,
+            frame_count: 0,
+            nmi_pending: false,
+            scanline_sprites: Vec::with_capacity(MAX_SPRITES_PER_SCANLINE),
+            frame_buffer: vec![0xFF; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+            emphasis_table: EmphasisTable::default(),
+            show_background: true,
+            show_sprites: true,
+        }
+    }
+
+    // Registers go back to their power-up values, but VRAM, OAM and the
+    // palettes keep their content
+    pub fn reset(&mut self) {
+        self.ctrl = 0;
+        self.mask = 0;
+        self.write_toggle.set(false);
+        self.read_buffer.set(0);
+        self.scanline = 0;
+        self.dot = 0;
+        self.nmi_pending = false;
+        self.scanline_sprites.clear();
+    }
+
+    // Keeps the configuration of the running PPU when loading a save state
+    pub fn restore_config(&mut self, running: &mut PPU) {
+        mem::swap(&mut self.emphasis_table, &mut running.emphasis_table);
+        self.show_background = running.show_background;
+        self.show_sprites = running.show_sprites;
+    }
+
+    pub fn set_show_background(&mut self, show: bool) {
+        self.show_background = show;
+    }
+
+    pub fn show_background(&self) -> bool {
+        self.show_background
+    }
+
+    pub fn set_show_sprites(&mut self, show: bool) {
+        self.show_sprites = show;
+    }
+
+    pub fn show_sprites(&self) -> bool {
+        self.show_sprites
+    }
+
+    // 256x240 RGBA pixels, row by row
+    pub fn frame_buffer(&self) -> &[u8] {
+        &self.frame_buffer
+    }
+
+    fn is_control_set(&self, flag: ControlFlag) -> bool {
+        self.ctrl & (1 << flag as u8) != 0
+    }
+
+    fn is_mask_set(&self, flag: MaskFlag) -> bool {
+        self.mask & (1 << flag as u8) != 0
+    }
+
+    fn set_status(&self, flag: StatusFlag, value: bool) {
+        let status: u8 = self.status.get();
+        if value {
+            self.status.set(status | 1 << flag as u8);
+        } else {
+            self.status.set(status & !(1 << flag as u8));
+        }
+    }
+
+    fn is_status_set(&self, flag: StatusFlag) -> bool {
+        self.status.get() & (1 << flag as u8) != 0
+    }
+
+    // Maps a nametable address (0x2000 - 0x2FFF) to VRAM according to the mirroring
+    fn get_vram_address(addr: u16, mirroring: Mirroring) -> usize {
+        let table: u16 = (addr & 0x0FFF) / NAMETABLE_SIZE;
+        let offset: u16 = addr % NAMETABLE_SIZE;
+        let bank: u16 = match mirroring {
+            Mirroring::Horizontal => table / 2,
+            Mirroring::Vertical => table % 2,
+            Mirroring::FourScreen => table,
+        };
+        (bank * NAMETABLE_SIZE + offset) as usize
+    }
+
+    fn read_nametable(&self, cartridge: &Cartridge, addr: u16) -> u8 {
+        self.vram[PPU::get_vram_address(addr, cartridge.mirroring())]
+    }
+
+    // PPU memory map:
+    // 0x0000 - 0x1FFF: pattern tables, on the cartridge
+    // 0x2000 - 0x2FFF: nametables, in VRAM
+    // 0x3000 - 0x3EFF: mirrors of 0x2000 - 0x2EFF
+    // 0x3F00 - 0x3FFF: palette RAM, 0x3F20 - 0x3FFF mirroring 0x3F00 - 0x3F1F
+    fn read_memory(&self, cartridge: &Cartridge, addr: u16) -> u8 {
+        match addr & PPU_ADDRESS_MASK {
+            0x0000..=0x1FFF => cartridge.chr_read(addr),
+            0x2000..=0x3EFF => self.read_nametable(cartridge, addr),
+            _ => {
+                let color: u8 = self.palette_ram[PPU::get_palette_address(addr)];
+                // greyscale also applies to palette reads
+                if self.is_mask_set(MaskFlag::Greyscale) {
+                    color & 0x30
+                } else {
+                    color
+                }
+            }
+        }
+    }
+
+    fn write_memory(&mut self, cartridge: &mut Cartridge, addr: u16, data: u8) {
+        match addr & PPU_ADDRESS_MASK {
+            0x0000..=0x1FFF => cartridge.chr_write(addr, data),
+            0x2000..=0x3EFF => self.vram[PPU::get_vram_address(addr, cartridge.mirroring())] = data,
+            // palette entries are 6 bits wide
+            _ => self.palette_ram[PPU::get_palette_address(addr)] = data & 0x3F,
+        }
+    }
+
+    // PPUDATA accesses move the address by 1, or by 32 (one row of tiles) if
+    // PPUCTRL bit 2 is set
+    fn increment_vram_addr(&self) {
+        let increment: u16 = if self.is_control_set(ControlFlag::VramIncrement) {
+            32
+        } else {
+            1
+        };
+        let addr: u16 = self.vram_addr.get();
+        self.vram_addr
+            .set(addr.wrapping_add(increment) & PPU_ADDRESS_MASK);
+    }
+
+    // The entries 0x10, 0x14, 0x18 and 0x1C mirror the background ones at 0x00, 0x04, 0x08 and 0x0C
+    fn get_palette_address(addr: u16) -> usize {
+        let index: usize = (addr & 0x1F) as usize;
+        if index & 0x13 == 0x10 {
+            index & 0x0F
+        } else {
+            index
+        }
+    }
+
+    // 2-bit color of a pixel of a tile, 0 being transparent
+    fn pattern_pixel(cartridge: &Cartridge, table: u16, tile: u8, row: u8, col: u8) -> u8 {
+        let addr: u16 = table + tile as u16 * 16 + row as u16;
+        let low: u8 = cartridge.chr_read(addr);
+        let high: u8 = cartridge.chr_read(addr + 8);
+        let bit: u8 = 7 - col;
+        ((high >> bit) & 1) << 1 | ((low >> bit) & 1)
+    }
+
+    fn is_rendering_enabled(&self) -> bool {
+        self.is_mask_set(MaskFlag::BackgroundEnabled) || self.is_mask_set(MaskFlag::SpritesEnabled)
+    }
+
+    // Whether the PPU is fetching tiles and sprites, on the visible and pre-render scanlines
+    fn is_rendering(&self) -> bool {
+        self.is_rendering_enabled()
+            && (self.scanline < SCREEN_HEIGHT as u16 || self.scanline == PRE_RENDER_SCANLINE)
+    }
+
+    // Moves v to the next row of pixels, wrapping to the nametable below
+    // after the 30 rows of tiles of a nametable
+    fn increment_y(&self) {
+        let mut addr: u16 = self.vram_addr.get();
+        if addr & FINE_Y_MASK != FINE_Y_MASK {
+            addr += 0x1000;
+        } else {
+            addr &= !FINE_Y_MASK;
+            let mut coarse_y: u16 = (addr & COARSE_Y_MASK) >> 5;
+            match coarse_y {
+                29 => {
+                    coarse_y = 0;
+                    addr ^= 0x0800;
+                }
+                // rows 30 and 31 hold the attributes, going past them does not
+                // switch nametable
+                31 => coarse_y = 0,
+                _ => coarse_y += 1,
+            }
+            addr = addr & !COARSE_Y_MASK | coarse_y << 5;
+        }
+        self.vram_addr.set(addr);
+    }
+
+    // Palette index (palette number * 4 + 2-bit color) of the background at x
+    // on the current scanline, scrolled according to v and the fine X scroll
+    fn background_pixel(&self, cartridge: &Cartridge, x: u16) -> u8 {
+        if !self.is_mask_set(MaskFlag::BackgroundEnabled)
+            || (x < 8 && !self.is_mask_set(MaskFlag::BackgroundLeftColumn))
+        {
+            return 0;
+        }
+        // v holds the scroll position at the start of the scanline
+        let addr: u16 = self.vram_addr.get();
+        let scroll_x: u16 = (addr & COARSE_X_MASK) * 8 + self.fine_x as u16 + x;
+        // going past the right edge continues on the next nametable
+        let nametable: u16 = (addr & NAMETABLE_MASK) ^ (scroll_x / 256) << 10;
+        let tile_x: u16 = (scroll_x / 8) % 32;
+        let tile_y: u16 = (addr & COARSE_Y_MASK) >> 5;
+        let row: u8 = ((addr & FINE_Y_MASK) >> 12) as u8;
+        let base: u16 = 0x2000 | nametable;
+        let tile: u8 = self.read_nametable(cartridge, base + tile_y * 32 + tile_x);
+        let table: u16 = if self.is_control_set(ControlFlag::BackgroundPatternTable) {
+            0x1000
+        } else {
+            0
+        };
+        let pixel: u8 = PPU::pattern_pixel(cartridge, table, tile, row, (scroll_x % 8) as u8);
+        // each attribute byte holds the palettes of 4 areas of 2x2 tiles
+        let attribute: u8 = self.read_nametable(
+            cartridge,
+            base + ATTRIBUTE_TABLE_OFFSET + (tile_y / 4) * 8 + tile_x / 4,
+        );
+        let shift: u16 = (tile_y & 0b10) * 2 + (tile_x & 0b10);
+        ((attribute >> shift) & 0b11) << 2 | pixel
+    }
+
+    // Front-most opaque sprite pixel at x on the current scanline, sprites earlier in OAM having priority
+    fn sprite_pixel(&self, cartridge: &Cartridge, x: u16) -> Option<(Sprite, u8)> {
+        if !self.is_mask_set(MaskFlag::SpritesEnabled)
+            || (x < 8 && !self.is_mask_set(MaskFlag::SpritesLeftColumn))
+        {
+            return None;
+        }
+        self.scanline_sprites.iter().find_map(|sprite| {
+            let mut col: u16 = x.wrapping_sub(sprite.x as u16);
+            if col >= 8 {
+                return None;
+            }
+            if sprite.attributes & (1 << SpriteAttribute::FlipHorizontally as u8) != 0 {
+                col = 7 - col;
+            }
+            let pixel: u8 =
+                PPU::pattern_pixel(cartridge, sprite.table, sprite.tile, sprite.row, col as u8);
+            (pixel != 0).then_some((*sprite, pixel))
+        })
+    }
+
+    // Selects the sprites of OAM that are visible on the next scanline
+    fn evaluate_sprites(&mut self) {
+        self.scanline_sprites.clear();
+        let is_8x16: bool = self.is_control_set(ControlFlag::SpriteSize);
+        let height: u16 = if is_8x16 { 16 } else { 8 };
+        for (index, entry) in self.oam.chunks_exact(4).enumerate() {
+            // sprites are drawn one scanline below their OAM Y coordinate
+            let mut row: u16 = self.scanline.wrapping_sub(entry[0] as u16);
+            if row >= height {
+                continue;
+            }
+            // the hardware evaluation is buggy past the eighth sprite and
+            // also sets the flag in some cases with eight sprites or less,
+            // only the correct behaviour is emulated
+            if self.scanline_sprites.len() == MAX_SPRITES_PER_SCANLINE {
+                if self.is_rendering_enabled() {
+                    self.set_status(StatusFlag::SpriteOverflow, true);
+                }
+                break;
+            }
+            let attributes: u8 = entry[2];
+            // flipping 8x16 sprites vertically also swaps their two tiles
+            if attributes & (1 << SpriteAttribute::FlipVertically as u8) != 0 {
+                row = height - 1 - row;
+            }
+            // 8x16 sprites take the pattern table from the lowest bit of the
+            // tile index, the top tile being the even one and the bottom
+            // tile the next one
+            let (table, tile): (u16, u8) = if is_8x16 {
+                (
+                    (entry[1] & 1) as u16 * 0x1000,
+                    (entry[1] & !1) + (row >= 8) as u8,
+                )
+            } else if self.is_control_set(ControlFlag::SpritePatternTable) {
+                (0x1000, entry[1])
+            } else {
+                (0, entry[1])
+            };
+            self.scanline_sprites.push(Sprite {
+                index,
+                x: entry[3],
+                row: (row % 8) as u8,
+                table,
+                tile,
+                attributes,
+            });
+        }
+    }
+
+    fn render_pixel(&mut self, cartridge: &Cartridge, x: u16, y: u16) {
+        let background: u8 = self.background_pixel(cartridge, x);
+        let sprite: Option<(Sprite, u8)> = self.sprite_pixel(cartridge, x);
+        // pixels are drawn left to right, top to bottom, so the flag is set
+        // at the first pixel where sprite 0 and the background are both opaque
+        if let Some((sprite, _)) = sprite {
+            if sprite.index == 0 && background & 0b11 != 0 && x != 255 {
+                self.set_status(StatusFlag::Sprite0Hit, true);
+            }
+        }
+        // the layers hidden for debugging are left out of the picture only,
+        // sprite 0 hits are still detected with them
+        let background: u8 = if self.show_background { background } else { 0 };
+        let is_background_opaque: bool = background & 0b11 != 0;
+        let sprite: Option<(Sprite, u8)> = sprite.filter(|_| self.show_sprites);
+        let palette_index: u8 = match sprite {
+            Some((sprite, pixel)) => {
+                let is_behind: bool =
+                    sprite.attributes & (1 << SpriteAttribute::BehindBackground as u8) != 0;
+                if is_behind && is_background_opaque {
+                    background
+                } else {
+                    SPRITE_PALETTES_OFFSET | (sprite.attributes & 0b11) << 2 | pixel
+                }
+            }
+            // transparent background pixels show the backdrop color
+            None if is_background_opaque => background,
+            None => 0,
+        };
+        let vram_addr: u16 = self.vram_addr.get();
+        // with rendering disabled, the backdrop is the palette entry v points
+        // to if it points to the palettes, which some games use to draw colors
+        let palette_addr: u16 =
+            if !self.is_rendering_enabled() && vram_addr >= PALETTE_START_ADDRESS {
+                vram_addr
+            } else {
+                palette_index as u16
+            };
+        let mut color: u8 = self.palette_ram[PPU::get_palette_address(palette_addr)];
+        // greyscale is applied from the current PPUMASK so it can change mid-scanline
+        if self.is_mask_set(MaskFlag::Greyscale) {
+            color &= 0x30;
+        }
+        // the emphasis bits are applied last, by the lookup in the emphasis
+        // table, so that the transform costs nothing per pixel
+        let rgb: Rgb = self.output_color(color);
+        let offset: usize = (y as usize * SCREEN_WIDTH + x as usize) * 4;
+        self.frame_buffer[offset..offset + 3].copy_from_slice(&rgb);
+    }
+
+    // Advances the PPU by one dot
+    pub fn tick(&mut self, cartridge: &mut Cartridge) {
+        let is_visible_scanline: bool = self.scanline < SCREEN_HEIGHT as u16;
+        if is_visible_scanline && (1..=SCREEN_WIDTH as u16).contains(&self.dot) {
+            self.render_pixel(cartridge, self.dot - 1, self.scanline);
+        }
+        if self.dot == SPRITE_EVALUATION_DOT {
+            if is_visible_scanline {
+                self.evaluate_sprites();
+            } else {
+                self.scanline_sprites.clear();
+            }
+        }
+        if self.scanline == VBLANK_SCANLINE && self.dot == 1 {
+            self.set_status(StatusFlag::VBlank, true);
+            if self.is_control_set(ControlFlag::NmiEnabled) {
+                self.nmi_pending = true;
+            }
+        }
+        if self.scanline == PRE_RENDER_SCANLINE && self.dot == 1 {
+            self.set_status(StatusFlag::VBlank, false);
+            self.set_status(StatusFlag::Sprite0Hit, false);
+            self.set_status(StatusFlag::SpriteOverflow, false);
+        }
+        if self.is_rendering() {
+            let addr: u16 = self.vram_addr.get();
+            match self.dot {
+                256 => self.increment_y(),
+                // back to the left of the screen for the next scanline
+                257 => {
+                    let horizontal: u16 = COARSE_X_MASK | NAMETABLE_MASK & 0x0400;
+                    self.vram_addr
+                        .set(addr & !horizontal | self.temp_addr & horizontal);
+                }
+                // back to the top of the screen for the next frame
+                280..=304 if self.scanline == PRE_RENDER_SCANLINE => {
+                    let vertical: u16 = FINE_Y_MASK | COARSE_Y_MASK | NAMETABLE_MASK & 0x0800;
+                    self.vram_addr
+                        .set(addr & !vertical | self.temp_addr & vertical);
+                }
+                _ => {}
+            }
+            // OAMADDR is cleared while the sprites of the next scanline are fetched
+            if (257..=320).contains(&self.dot) {
+                self.oam_addr = 0;
+            }
+            // A12 is high while fetching from the pattern table at 0x1000:
+            // it rises once per scanline, when the sprite fetches start at
+            // dot 257 or when the background fetches for the next scanline
+            // start at dot 321, the MMC3 seeing it a few dots later
+            // unused 8x16 sprite slots fetch tile 0xFF, from the table at 0x1000
+            let sprites_high: bool = self.is_control_set(ControlFlag::SpritePatternTable)
+                || self.is_control_set(ControlFlag::SpriteSize);
+            let background_high: bool = self.is_control_set(ControlFlag::BackgroundPatternTable);
+            if (self.dot == 260 && sprites_high && !background_high)
+                || (self.dot == 324 && background_high && !sprites_high)
+            {
+                cartridge.notify_a12_rise();
+            }
+        }
+        self.dot += 1;
+        if self.dot == DOTS_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline = (self.scanline + 1) % SCANLINES_PER_FRAME;
+            if self.scanline == 0 {
+                self.frame_count += 1;
+            }
+        }
+    }
+
+    // Returns true once for every NMI raised at the start of the vertical blank
+    pub fn take_nmi(&mut self) -> bool {
+        mem::take(&mut self.nmi_pending)
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    pub fn dot(&self) -> u16 {
+        self.dot
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    // The 64 sprites of 4 bytes: Y, tile index, attributes and X
+    pub fn oam(&self) -> &[u8; 0x100] {
+        &self.oam
+    }
+
+    // Replaces the color emphasis transformation, the built-in one is EmphasisTable::default()
+    pub fn set_emphasis_table(&mut self, table: EmphasisTable) {
+        self.emphasis_table = table;
+    }
+
+    // RGB colors of the 8 palettes of 4 colors, backgrounds first, as they
+    // would be output with the current PPUMASK
+    pub fn palettes(&self) -> [Rgb; 0x20] {
+        let mut palettes: [Rgb; 0x20] = [[0; 3]; 0x20];
+        for (index, rgb) in palettes.iter_mut().enumerate() {
+            let mut color: u8 = self.palette_ram[PPU::get_palette_address(index as u16)];
+            if self.is_mask_set(MaskFlag::Greyscale) {
+                color &= 0x30;
+            }
+            *rgb = self.output_color(color);
+        }
+        palettes
+    }
+
+    // Converts a 6-bit palette color to the RGB color output with the current PPUMASK emphasis
+    pub fn output_color(&self, color: u8) -> Rgb {
+        self.emphasis_table.get(color, self.mask >> EMPHASIS_SHIFT)
+    }
+
+    pub fn read_register(&self, cartridge: &Cartridge, addr: u16) -> u8 {
+        match addr {
+            // clears VBlank and w, sprite 0 hit and overflow stay set until
+            // the pre-render scanline
+            PPUSTATUS => {
+                let status: u8 = self.status.get();
+                self.set_status(StatusFlag::VBlank, false);
+                self.write_toggle.set(false);
+                status
+            }
+            // the PPU uses OAM while rendering: reads return what it is
+            // working on, approximated here by the 0xFF it fills the
+            // secondary OAM with during dots 1 - 64 and by OAM otherwise
+            OAMDATA
+                if self.is_rendering()
+                    && self.scanline < SCREEN_HEIGHT as u16
+                    && (1..=64).contains(&self.dot) =>
+            {
+                0xFF
+            }
+            OAMDATA => {
+                let value: u8 = self.oam[self.oam_addr as usize];
+                // bits 2 - 4 of the sprite attributes do not exist
+                if self.oam_addr % 4 == 2 {
+                    value & 0b11100011
+                } else {
+                    value
+                }
+            }
+            PPUDATA => {
+                let vram_addr: u16 = self.vram_addr.get();
+                let value: u8 = self.read_memory(cartridge, vram_addr);
+                self.increment_vram_addr();
+                if vram_addr < PALETTE_START_ADDRESS {
+                    self.read_buffer.replace(value)
+                } else {
+                    // palette reads are not delayed, but still fill the buffer
+                    // with the nametable byte under the palette
+                    self.read_buffer
+                        .set(self.read_memory(cartridge, vram_addr - 0x1000));
+                    value
+                }
+            }
+            // the other registers are write-only, RAM::read returns the open bus for them
+            _ => 0,
+        }
+    }
+
+    pub fn write_register(&mut self, cartridge: &mut Cartridge, addr: u16, data: u8) {
+        match addr {
+            PPUCTRL => {
+                // enabling NMIs during the vertical blank raises one right away
+                let was_nmi_enabled: bool = self.is_control_set(ControlFlag::NmiEnabled);
+                self.ctrl = data;
+                if !was_nmi_enabled
+                    && self.is_control_set(ControlFlag::NmiEnabled)
+                    && self.is_status_set(StatusFlag::VBlank)
+                {
+                    self.nmi_pending = true;
+                }
+                self.temp_addr = self.temp_addr & !NAMETABLE_MASK | ((data & 0b11) as u16) << 10;
+            }
+            PPUMASK => self.mask = data,
+            OAMADDR => self.oam_addr = data,
+            OAMDATA => {
+                if self.is_rendering() {
+                    // the write is lost, but OAMADDR still moves to the next sprite
+                    self.oam_addr = self.oam_addr.wrapping_add(4);
+                } else {
+                    self.oam[self.oam_addr as usize] = data;
+                    self.oam_addr = self.oam_addr.wrapping_add(1);
+                }
+            }
+            // X scroll first, then Y scroll
+            PPUSCROLL => {
+                if self.write_toggle.get() {
+                    self.temp_addr = self.temp_addr & !(FINE_Y_MASK | COARSE_Y_MASK)
+                        | ((data & 0b111) as u16) << 12
+                        | ((data >> 3) as u16) << 5;
+                } else {
+                    self.temp_addr = self.temp_addr & !COARSE_X_MASK | (data >> 3) as u16;
+                    self.fine_x = data & 0b111;
+                }
+                self.write_toggle.set(!self.write_toggle.get());
+            }
+            // high byte first, then low byte, which copies t into v
+            PPUADDR => {
+                if self.write_toggle.get() {
+                    self.temp_addr = self.temp_addr & 0xFF00 | data as u16;
+                    self.vram_addr.set(self.temp_addr);
+                } else {
+                    self.temp_addr =
+                        ((data as u16) << 8 | self.temp_addr & 0x00FF) & PPU_ADDRESS_MASK;
+                }
+                self.write_toggle.set(!self.write_toggle.get());
+            }
+            PPUDATA => {
+                self.write_memory(cartridge, self.vram_addr.get(), data);
+                self.increment_vram_addr();
+            }
+            // PPUSTATUS is read-only
+            _ => {}
+        }
+    }
+}
+
+impl Default for PPU {
+    fn default() -> PPU {
+        PPU::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom;
+
+    // Writes data through PPUADDR and PPUDATA, then points v back to 0x0000
+    // so that rendering starts from the top left of the first nametable
+    fn write_vram(ppu: &mut PPU, cartridge: &mut Cartridge, addr: u16, data: &[u8]) {
+        ppu.write_register(cartridge, PPUADDR, (addr >> 8) as u8);
+        ppu.write_register(cartridge, PPUADDR, addr as u8);
+        for &byte in data {
+            ppu.write_register(cartridge, PPUDATA, byte);
+        }
+        ppu.write_register(cartridge, PPUADDR, 0);
+        ppu.write_register(cartridge, PPUADDR, 0);
+    }
+
+    fn write_sprite(ppu: &mut PPU, cartridge: &mut Cartridge, index: u8, entry: [u8; 4]) {
+        ppu.write_register(cartridge, OAMADDR, index * 4);
+        for byte in entry {
+            ppu.write_register(cartridge, OAMDATA, byte);
+        }
+    }
+
+    // Ticks until the condition holds, at most one frame
+    fn tick_until(ppu: &mut PPU, cartridge: &mut Cartridge, condition: impl Fn(&PPU) -> bool) {
+        for _ in 0..DOTS_PER_SCANLINE as u32 * SCANLINES_PER_FRAME as u32 {
+            ppu.tick(cartridge);
+            if condition(ppu) {
+                return;
+            }
+        }
+        panic!("condition not met within a frame");
+    }
+
+    #[test]
+    fn custom_emphasis_table_gives_the_output_color() {
+        let mut cartridge: Cartridge = test_rom::cartridge(0);
+        let mut ppu: PPU = PPU::new();
+        ppu.set_emphasis_table(EmphasisTable::from_fn(|color, emphasis| {
+            [color, emphasis, 0xFF]
+        }));
+        assert_eq!(ppu.output_color(0x21), [0x21, 0, 0xFF]);
+        // red and blue emphasis
+        ppu.write_register(&mut cartridge, PPUMASK, 0b101 << EMPHASIS_SHIFT);
+        assert_eq!(ppu.output_color(0x21), [0x21, 0b101, 0xFF]);
+    }
+
+    #[test]
+    fn red_emphasis_dims_green_and_blue() {
+        let mut cartridge: Cartridge = test_rom::cartridge(0);
+        let mut ppu: PPU = PPU::new();
+        let [red, green, blue]: Rgb = MASTER_PALETTE[0x30];
+        ppu.write_register(&mut cartridge, PPUMASK, 0b001 << EMPHASIS_SHIFT);
+        assert_eq!(
+            ppu.output_color(0x30),
+            [
+                red,
+                (green as f32 * EMPHASIS_ATTENUATION) as u8,
+                (blue as f32 * EMPHASIS_ATTENUATION) as u8,
+            ]
+        );
+        ppu.write_register(&mut cartridge, PPUMASK, 0);
+        assert_eq!(ppu.output_color(0x30), MASTER_PALETTE[0x30]);
+    }
+
+    #[test]
+    fn sprite_0_hit_is_set_at_the_first_overlap_in_rendering_order() {
+        let mut cartridge: Cartridge = test_rom::cartridge(0);
+        let mut ppu: PPU = PPU::new();
+        // tile 1 is opaque everywhere, tile 2 only at column 5 of row 0 and
+        // column 2 of row 1
+        write_vram(&mut ppu, &mut cartridge, 0x0010, &[0xFF; 8]);
+        write_vram(&mut ppu, &mut cartridge, 0x0020, &[0b00000100, 0b00100000]);
+        // tile 2 at x 16 - 23, y 32 - 39
+        write_vram(&mut ppu, &mut cartridge, 0x2082, &[2]);
+        // sprite 0 with tile 1 over the same area; of the two overlaps, the
+        // one on the first scanline comes first even though it is further right
+        write_sprite(&mut ppu, &mut cartridge, 0, [31, 1, 0, 16]);
+        ppu.write_register(&mut cartridge, PPUMASK, 0b00011110);
+        tick_until(&mut ppu, &mut cartridge, |ppu| {
+            ppu.is_status_set(StatusFlag::Sprite0Hit)
+        });
+        // x = 21 is drawn at dot 22
+        assert_eq!((ppu.scanline(), ppu.dot() - 1), (32, 22));
+    }
+
+    #[test]
+    fn greyscale_toggled_mid_scanline_only_affects_later_pixels() {
+        let mut cartridge: Cartridge = test_rom::cartridge(0);
+        let mut ppu: PPU = PPU::new();
+        write_vram(&mut ppu, &mut cartridge, PALETTE_START_ADDRESS, &[0x21]);
+        ppu.write_register(&mut cartridge, PPUMASK, 0b00001010);
+        tick_until(&mut ppu, &mut cartridge, |ppu| {
+            (ppu.scanline(), ppu.dot()) == (0, 100)
+        });
+        ppu.write_register(&mut cartridge, PPUMASK, 0b00001011);
+        tick_until(&mut ppu, &mut cartridge, |ppu| ppu.scanline() == 1);
+        let pixel = |x: usize| &ppu.frame_buffer()[x * 4..x * 4 + 3];
+        assert_eq!(pixel(98), ppu.output_color(0x21));
+        assert_eq!(pixel(99), ppu.output_color(0x20));
+        assert_eq!(pixel(SCREEN_WIDTH - 1), ppu.output_color(0x20));
+    }
+
+    #[test]
+    fn ppudata_increments_by_1_or_32_and_buffers_reads() {
+        let mut cartridge: Cartridge = test_rom::cartridge(0);
+        let mut ppu: PPU = PPU::new();
+        write_vram(&mut ppu, &mut cartridge, 0x2000, &[0x11, 0x22]);
+        // increment by 32
+        ppu.write_register(&mut cartridge, PPUCTRL, 0b100);
+        write_vram(&mut ppu, &mut cartridge, 0x2100, &[0x33, 0x44]);
+        assert_eq!(ppu.read_memory(&cartridge, 0x2000), 0x11);
+        assert_eq!(ppu.read_memory(&cartridge, 0x2001), 0x22);
+        assert_eq!(ppu.read_memory(&cartridge, 0x2100), 0x33);
+        assert_eq!(ppu.read_memory(&cartridge, 0x2120), 0x44);
+
+        ppu.write_register(&mut cartridge, PPUCTRL, 0);
+        ppu.write_register(&mut cartridge, PPUADDR, 0x20);
+        ppu.write_register(&mut cartridge, PPUADDR, 0x00);
+        // the first read returns the buffer filled by the previous one
+        ppu.read_register(&cartridge, PPUDATA);
+        assert_eq!(ppu.read_register(&cartridge, PPUDATA), 0x11);
+        assert_eq!(ppu.read_register(&cartridge, PPUDATA), 0x22);
+        assert_eq!(ppu.vram_addr.get(), 0x2003);
+    }
+
+    #[test]
+    fn two_ppuscroll_writes_set_the_fine_and_coarse_scrolls() {
+        let mut cartridge: Cartridge = test_rom::cartridge(0);
+        let mut ppu: PPU = PPU::new();
+        // second nametable
+        ppu.write_register(&mut cartridge, PPUCTRL, 0b01);
+        // X = 125 = 15 * 8 + 5, Y = 94 = 11 * 8 + 6
+        ppu.write_register(&mut cartridge, PPUSCROLL, 125);
+        ppu.write_register(&mut cartridge, PPUSCROLL, 94);
+        assert_eq!(ppu.fine_x, 5);
+        // fine Y, nametable, coarse Y and coarse X
+        assert_eq!(ppu.temp_addr, 6 << 12 | 0b01 << 10 | 11 << 5 | 15);
+        assert!(!ppu.write_toggle.get());
+    }
+
+    #[test]
+    fn four_screen_nametables_do_not_alias() {
+        let mut cartridge: Cartridge = test_rom::cartridge(0b1000);
+        let mut ppu: PPU = PPU::new();
+        let nametables: [u16; 4] = [0x2000, 0x2400, 0x2800, 0x2C00];
+        for (i, &addr) in nametables.iter().enumerate() {
+            write_vram(&mut ppu, &mut cartridge, addr + 0x3FF, &[i as u8 + 1]);
+        }
+        for (i, &addr) in nametables.iter().enumerate() {
+            assert_eq!(ppu.read_memory(&cartridge, addr + 0x3FF), i as u8 + 1);
+        }
+        // 0x3000 - 0x3EFF mirrors the nametables
+        assert_eq!(ppu.read_memory(&cartridge, 0x2BFF + 0x1000), 3);
+    }
+
+    #[test]
+    fn disabled_background_leaves_the_backdrop_color() {
+        let mut cartridge: Cartridge = test_rom::cartridge(0);
+        let mut ppu: PPU = PPU::new();
+        // tile 1 is opaque everywhere and covers the first row of tiles
+        write_vram(&mut ppu, &mut cartridge, 0x0010, &[0xFF; 8]);
+        write_vram(&mut ppu, &mut cartridge, 0x2000, &[1; 32]);
+        write_vram(
+            &mut ppu,
+            &mut cartridge,
+            PALETTE_START_ADDRESS,
+            &[0x21, 0x16],
+        );
+        // sprites only, so that rendering stays enabled
+        ppu.write_register(&mut cartridge, PPUMASK, 0b00010100);
+        tick_until(&mut ppu, &mut cartridge, |ppu| ppu.scanline() == 1);
+        let backdrop: Rgb = ppu.output_color(0x21);
+        assert!(ppu.frame_buffer()[..SCREEN_WIDTH * 4]
+            .chunks_exact(4)
+            .all(|pixel| pixel[..3] == backdrop));
+    }
+
+    #[test]
+    fn ninth_sprite_on_a_scanline_sets_sprite_overflow() {
+        for sprite_count in [8, 9] {
+            let mut cartridge: Cartridge = test_rom::cartridge(0);
+            let mut ppu: PPU = PPU::new();
+            // the other sprites are below the screen
+            ppu.oam = [0xFF; 0x100];
+            for index in 0..sprite_count {
+                write_sprite(&mut ppu, &mut cartridge, index, [40, 0, 0, index * 8]);
+            }
+            ppu.write_register(&mut cartridge, PPUMASK, 0b00010100);
+            tick_until(&mut ppu, &mut cartridge, |ppu| {
+                ppu.scanline() == SCREEN_HEIGHT as u16
+            });
+            assert_eq!(
+                ppu.is_status_set(StatusFlag::SpriteOverflow),
+                sprite_count == 9
+            );
+        }
+    }
+
+    #[test]
+    fn vertical_flip_of_8x16_sprites_swaps_their_tiles() {
+        let mut rows: Vec<usize> = Vec::new();
+        for attributes in [0, 1 << SpriteAttribute::FlipVertically as u8] {
+            let mut cartridge: Cartridge = test_rom::cartridge(0);
+            let mut ppu: PPU = PPU::new();
+            ppu.oam = [0xFF; 0x100];
+            // only the first row of tile 2, the top tile of the sprite, is opaque
+            write_vram(&mut ppu, &mut cartridge, 0x0020, &[0xFF]);
+            write_vram(&mut ppu, &mut cartridge, 0x3F11, &[0x16]);
+            write_sprite(&mut ppu, &mut cartridge, 0, [40, 2, attributes, 100]);
+            // 8x16 sprites
+            ppu.write_register(&mut cartridge, PPUCTRL, 0b00100000);
+            ppu.write_register(&mut cartridge, PPUMASK, 0b00010100);
+            tick_until(&mut ppu, &mut cartridge, |ppu| {
+                ppu.scanline() == SCREEN_HEIGHT as u16
+            });
+            let sprite_color: Rgb = ppu.output_color(0x16);
+            let sprite_rows: Vec<usize> = (0..SCREEN_HEIGHT)
+                .filter(|y| {
+                    let offset: usize = (y * SCREEN_WIDTH + 100) * 4;
+                    ppu.frame_buffer()[offset..offset + 3] == sprite_color
+                })
+                .collect();
+            assert_eq!(sprite_rows.len(), 1);
+            rows.push(sprite_rows[0]);
+        }
+        assert_eq!(rows, [41, 41 + 15]);
+    }
+
+    #[test]
+    fn oamdata_writes_read_back_at_oamaddr() {
+        let mut cartridge: Cartridge = test_rom::cartridge(0);
+        let mut ppu: PPU = PPU::new();
+        write_sprite(&mut ppu, &mut cartridge, 5, [0x10, 0x20, 0xFF, 0x40]);
+        assert_eq!(ppu.oam_addr, 24);
+        let mut entry: Vec<u8> = Vec::new();
+        for addr in 20..24 {
+            ppu.write_register(&mut cartridge, OAMADDR, addr);
+            entry.push(ppu.read_register(&cartridge, OAMDATA));
+        }
+        // bits 2 - 4 of the attributes read as 0
+        assert_eq!(entry, [0x10, 0x20, 0b11100011, 0x40]);
+        // reads do not move OAMADDR
+        assert_eq!(ppu.oam_addr, 23);
+    }
+
+    #[test]
+    fn status_flags_are_cleared_at_dot_1_of_the_pre_render_scanline() {
+        let mut cartridge: Cartridge = test_rom::cartridge(0);
+        let mut ppu: PPU = PPU::new();
+        ppu.status.set(0b11100000);
+        tick_until(&mut ppu, &mut cartridge, |ppu| {
+            (ppu.scanline(), ppu.dot()) == (PRE_RENDER_SCANLINE, 1)
+        });
+        assert_eq!(ppu.status.get(), 0b11100000);
+        ppu.tick(&mut cartridge);
+        assert_eq!(ppu.status.get(), 0);
+    }
+
+    #[test]
+    fn hidden_sprite_layer_is_left_out_of_the_picture_only() {
+        for show_sprites in [true, false] {
+            let mut cartridge: Cartridge = test_rom::cartridge(0);
+            let mut ppu: PPU = PPU::new();
+            ppu.set_show_sprites(show_sprites);
+            // sprite 0 with the opaque tile 1 over the same tile of the
+            // background at x 16 - 23, y 32 - 39
+            write_vram(&mut ppu, &mut cartridge, 0x0010, &[0xFF; 8]);
+            write_vram(&mut ppu, &mut cartridge, 0x2082, &[1]);
+            write_vram(&mut ppu, &mut cartridge, 0x3F01, &[0x16]);
+            write_vram(&mut ppu, &mut cartridge, 0x3F11, &[0x2A]);
+            write_sprite(&mut ppu, &mut cartridge, 0, [31, 1, 0, 16]);
+            ppu.write_register(&mut cartridge, PPUMASK, 0b00011110);
+            tick_until(&mut ppu, &mut cartridge, |ppu| ppu.scanline() == 40);
+            let offset: usize = (32 * SCREEN_WIDTH + 16) * 4;
+            let color: u8 = if show_sprites { 0x2A } else { 0x16 };
+            assert_eq!(
+                ppu.frame_buffer()[offset..offset + 3],
+                ppu.output_color(color)
+            );
+            assert!(ppu.is_status_set(StatusFlag::Sprite0Hit));
+        }
+    }
+}
@@ -1,6 +1,27 @@
 use std::{
-    fs::File,
-    io::{Read, Seek, SeekFrom},
+    cell::Cell,
+    collections::HashMap,
+    fs, mem,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_big_array::BigArray;
+
+#[cfg(feature = "apu")]
+use crate::{
+    apu::APU,
+    mem::{APU_CHANNELS_END, APU_FRAME_COUNTER, APU_REG_START},
+};
+use crate::{
+    bus::Bus,
+    cartridge::{Cartridge, Header, RomError, HEADER_SIZE, TRAINER_SIZE},
+    controller::Controller,
+    mem::{
+        APU_STATUS, CARTRIDGE_START, JOY1, JOY2, OAMDATA, OAM_DMA, PPUDATA, PPUSTATUS,
+        PPU_REG_COUNT, PPU_REG_END, PPU_REG_START, UNMAPPED_START, WORK_RAM_END, WORK_RAM_SIZE,
+    },
+    ppu::PPU,
 };
 
 // Memory map:
@@ -13,71 +34,477 @@ use std::{
 // 0x4000 - 0x4017: APU and I/O registers
 // 0x4018 - 0x401F: APU and I/O functionality that is normally disabled
 // 0x4020 - 0xFFFF: Cartridge space: PRG ROM, PRG RAM, and mapper registers
+//
+// Reads of addresses nothing drives return the open bus, the last value seen
+// on the data bus, which is usually the last byte of the instruction operand:
+// 0x2000, 0x2001, 0x2003, 0x2005, 0x2006: write-only PPU registers
+// 0x2002: bits 0 - 4 of PPUSTATUS
+// 0x4000 - 0x4014: write-only APU registers and OAMDMA (with the apu feature)
+// 0x4015: bit 5 of the APU status, the read does not update the open bus
+// 0x4016 - 0x4017: bits 5 - 7, the controllers only drive the low bits
+// 0x4018 - 0x5FFF: unmapped
+// Everything else, RAM and cartridge space, returns defined values
 
-const CPU_ROM_START_ADDRESS: usize = 0x8000;
-const PRG_PAGE_SIZE: usize = 0x4000;
-const CHR_PAGE_SIZE: usize = 0x2000;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
 
-#[derive(Debug)]
+impl WatchKind {
+    fn matches(&self, access: Access) -> bool {
+        matches!(
+            (self, access),
+            (WatchKind::ReadWrite, _)
+                | (WatchKind::Read, Access::Read)
+                | (WatchKind::Write, Access::Write)
+        )
+    }
+}
+
+// Content of the 2KB of work RAM at power on, which is undefined on the console
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PowerOnRam {
+    // all bytes 0x00
+    #[default]
+    Zero,
+    // 4 bytes 0x00 then 4 bytes 0xFF, repeated, the pattern most often seen
+    // on real consoles
+    Pattern,
+    // the output of SplitMix64 from the given seed, each 64-bit value giving
+    // 8 bytes in little endian order; the same seed always gives the same
+    // content
+    Random(u64),
+}
+
+// Access that triggered a watchpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub addr: u16,
+    pub value: u8,
+    pub access: Access,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct RAM {
-    ram: [u8; 0x10000],
+    #[serde(with = "BigArray")]
+    ram: [u8; CARTRIDGE_START as usize],
+    ppu: PPU,
+    #[cfg(feature = "apu")]
+    apu: APU,
+    controllers: [Controller; 2],
+    cartridge: Cartridge,
+    // last value read or written by the CPU
+    open_bus: Cell<u8>,
+    // set by a write to OAMDMA, the CPU is halted while the copy runs
+    oam_dma_pending: bool,
+    // debugger state, not part of save states
+    #[serde(skip)]
+    watchpoints: HashMap<u16, WatchKind>,
+    // first watchpoint hit since the last call to take_watch_hit; a Cell
+    // since reads only borrow the RAM immutably
+    #[serde(skip)]
+    watch_hit: Cell<Option<WatchHit>>,
 }
 
 impl RAM {
-    pub fn new() -> RAM {
-        RAM { ram: [0; 0x10000] }
-    }
-
-    pub fn from_file(file_path: &str) -> RAM {
-        let mut ram: RAM = RAM::new();
-        let mut file: File = File::open(file_path).unwrap();
-        let mut header: [u8; 16] = [0; 16];
-        file.read_exact(&mut header).unwrap();
-        if header[..4] != [0x4E, 0x45, 0x53, 0x1A] {
-            panic!("Invalid NES file");
-        }
-        let prg_rom_size: usize;
-        let _chr_rom_size: usize;
-        let is_nes_2_0: bool = (header[7] & 0x0C) == 0x08;
-        let has_trainer: bool = (header[6] & 0b00000100) != 0;
-        if is_nes_2_0 {
-            prg_rom_size =
-                (((header[9] & 0b1111) as usize) << 8 | header[4] as usize) * PRG_PAGE_SIZE;
-            _chr_rom_size =
-                (((header[9] & 0b11110000) as usize) << 4 | header[5] as usize) * CHR_PAGE_SIZE;
-        } else {
-            prg_rom_size = header[4] as usize * PRG_PAGE_SIZE;
-            _chr_rom_size = header[5] as usize * CHR_PAGE_SIZE;
-        }
-        // always skip trainer
+    pub fn new(cartridge: Cartridge) -> RAM {
+        RAM {
+            ram: [0; CARTRIDGE_START as usize],
+            ppu: PPU::new(),
+            #[cfg(feature = "apu")]
+            apu: APU::new(),
+            controllers: [Controller::new(), Controller::new()],
+            cartridge,
+            open_bus: Cell::new(0),
+            oam_dma_pending: false,
+            watchpoints: HashMap::new(),
+            watch_hit: Cell::new(None),
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(file_path: P) -> Result<RAM, RomError> {
+        let data: Vec<u8> = fs::read(&file_path).map_err(RomError::Io)?;
+        // battery-backed PRG RAM is persisted next to the ROM
+        let save_path: PathBuf = file_path.as_ref().with_extension("sav");
+        RAM::parse(&data, Some(save_path))
+    }
+
+    // Loads an iNES ROM that is already in memory; PRG RAM is not persisted
+    pub fn from_bytes(data: &[u8]) -> Result<RAM, RomError> {
+        RAM::parse(data, None)
+    }
+
+    fn parse(data: &[u8], save_path: Option<PathBuf>) -> Result<RAM, RomError> {
+        let header: Header = Header::from_rom(data)?;
+        header.validate(data.len())?;
+        let mut offset: usize = HEADER_SIZE;
+        // the trainer sits between the header and the PRG ROM
+        if header.has_trainer {
+            offset += TRAINER_SIZE;
+        }
+        let prg_rom_end: usize = offset + header.prg_rom_size;
+        let chr_rom_end: usize = prg_rom_end + header.chr_rom_size;
+        let prg_rom: Vec<u8> = data[offset..prg_rom_end].to_vec();
+        let chr_rom: Vec<u8> = data[prg_rom_end..chr_rom_end].to_vec();
+        let has_trainer: bool = header.has_trainer;
+        let mut cartridge: Cartridge = Cartridge::new(header, prg_rom, chr_rom, save_path);
         if has_trainer {
-            file.seek(SeekFrom::Current(512)).unwrap();
+            cartridge.load_trainer(&data[HEADER_SIZE..HEADER_SIZE + TRAINER_SIZE]);
+        }
+        Ok(RAM::new(cartridge))
+    }
+
+    // Moves what is not part of save states from the running RAM
+    pub fn restore(&mut self, running: &mut RAM) {
+        mem::swap(&mut self.watchpoints, &mut running.watchpoints);
+        self.ppu.restore_config(&mut running.ppu);
+        self.cartridge.restore_rom(&mut running.cartridge);
+    }
+
+    pub fn cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    pub fn fill_work_ram(&mut self, power_on: PowerOnRam) {
+        match power_on {
+            PowerOnRam::Zero => self.work_ram_mut().fill(0),
+            PowerOnRam::Pattern => {
+                for (i, byte) in self.work_ram_mut().iter_mut().enumerate() {
+                    *byte = if i & 0b100 == 0 { 0x00 } else { 0xFF };
+                }
+            }
+            PowerOnRam::Random(seed) => {
+                let mut state: u64 = seed;
+                for chunk in self.work_ram_mut().chunks_exact_mut(8) {
+                    state = state.wrapping_add(0x9E3779B97F4A7C15);
+                    let mut value: u64 = state;
+                    value = (value ^ (value >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+                    value = (value ^ (value >> 27)).wrapping_mul(0x94D049BB133111EB);
+                    value ^= value >> 31;
+                    chunk.copy_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    // The 2KB of internal RAM at 0x0000 - 0x07FF, without its mirrors
+    pub fn work_ram(&self) -> &[u8] {
+        &self.ram[..WORK_RAM_SIZE]
+    }
+
+    pub fn work_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram[..WORK_RAM_SIZE]
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        &self.ppu
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut PPU {
+        &mut self.ppu
+    }
+
+    #[cfg(feature = "apu")]
+    pub fn apu(&self) -> &APU {
+        &self.apu
+    }
+
+    #[cfg(feature = "apu")]
+    pub fn apu_mut(&mut self) -> &mut APU {
+        &mut self.apu
+    }
+
+    // port is 0 or 1, panics otherwise
+    pub fn controller(&self, port: u8) -> &Controller {
+        &self.controllers[port as usize]
+    }
+
+    pub fn controller_mut(&mut self, port: u8) -> &mut Controller {
+        &mut self.controllers[port as usize]
+    }
+
+    // Advances the PPU by one dot
+    pub fn tick_ppu(&mut self) {
+        self.ppu.tick(&mut self.cartridge);
+    }
+
+    // Returns true once after each OAM DMA, for the CPU to count the cycles
+    // it was halted
+    pub fn take_oam_dma(&mut self) -> bool {
+        mem::take(&mut self.oam_dma_pending)
+    }
+
+    // Copies 0xXX00 - 0xXXFF to the PPU OAM through OAMDATA, so the copy
+    // starts at OAMADDR
+    fn oam_dma(&mut self, page: u8) {
+        let start: u16 = (page as u16) << 8;
+        for offset in 0..=0xFF {
+            let value: u8 = self.read(start + offset);
+            self.ppu.write_register(&mut self.cartridge, OAMDATA, value);
         }
-        // TODO handle more than 2 pages of PRG ROM
-        file.read_exact(&mut ram.ram[CPU_ROM_START_ADDRESS..CPU_ROM_START_ADDRESS + prg_rom_size])
-            .unwrap();
-        if prg_rom_size == PRG_PAGE_SIZE {
-            ram.ram.copy_within(
-                CPU_ROM_START_ADDRESS..CPU_ROM_START_ADDRESS + PRG_PAGE_SIZE,
-                0xC000,
-            );
+        self.oam_dma_pending = true;
+    }
+
+    // Level of the IRQ line shared by the APU and the cartridge
+    pub fn irq(&self) -> bool {
+        #[cfg(feature = "apu")]
+        if self.apu.irq() {
+            return true;
         }
-        ram
+        self.cartridge.irq()
     }
 
     fn get_ram_address(addr: u16) -> usize {
         match addr {
-            0x0800..=0x1FFF => (addr % 0x0800) as usize,
-            0x2008..=0x3FFF => 0x2000 + (addr % 0x0008) as usize,
+            ..=WORK_RAM_END => addr as usize % WORK_RAM_SIZE,
+            PPU_REG_START..=PPU_REG_END => (PPU_REG_START + addr % PPU_REG_COUNT) as usize,
             _ => addr as usize,
         }
     }
 
+    // Watchpoints are keyed by the CPU address, mirrors are not watched
+    pub fn add_watchpoint(&mut self, addr: u16, kind: WatchKind) {
+        self.watchpoints.insert(addr, kind);
+    }
+
+    pub fn remove_watchpoint(&mut self, addr: u16) {
+        self.watchpoints.remove(&addr);
+    }
+
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    fn check_watchpoint(&self, addr: u16, value: u8, access: Access) {
+        if self.watch_hit.get().is_some() {
+            return;
+        }
+        if let Some(kind) = self.watchpoints.get(&addr) {
+            if kind.matches(access) {
+                self.watch_hit.set(Some(WatchHit {
+                    addr,
+                    value,
+                    access,
+                }));
+            }
+        }
+    }
+
+    pub fn open_bus(&self) -> u8 {
+        self.open_bus.get()
+    }
+
+    // Reads a byte without side effects, for debuggers; the registers are not
+    // read, since reading PPUSTATUS, PPUDATA, the APU status or the
+    // controllers changes their state, and the open bus value is returned instead
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            PPU_REG_START..CARTRIDGE_START => self.open_bus.get(),
+            CARTRIDGE_START.. => self.cartridge.read(addr),
+            _ => self.ram[RAM::get_ram_address(addr)],
+        }
+    }
+
+    // Copies len bytes from start with peek, wrapping around after 0xFFFF
+    pub fn dump(&self, start: u16, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| self.peek(start.wrapping_add(i as u16)))
+            .collect()
+    }
+
     pub fn read(&self, addr: u16) -> u8 {
-        self.ram[RAM::get_ram_address(addr)]
+        let open_bus: u8 = self.open_bus.get();
+        let value: u8 = match addr {
+            PPU_REG_START..=PPU_REG_END => match RAM::get_ram_address(addr) as u16 {
+                PPUSTATUS => {
+                    self.ppu.read_register(&self.cartridge, PPUSTATUS) & 0b11100000
+                        | open_bus & 0b00011111
+                }
+                register @ (OAMDATA | PPUDATA) => self.ppu.read_register(&self.cartridge, register),
+                _ => open_bus,
+            },
+            #[cfg(feature = "apu")]
+            APU_REG_START..=OAM_DMA => open_bus,
+            #[cfg(feature = "apu")]
+            APU_STATUS => self.apu.read_register(addr) & !0b00100000 | open_bus & 0b00100000,
+            JOY1 => self.controllers[0].read() | open_bus & 0b11100000,
+            JOY2 => self.controllers[1].read() | open_bus & 0b11100000,
+            UNMAPPED_START..CARTRIDGE_START => open_bus,
+            CARTRIDGE_START.. => self.cartridge.read(addr),
+            _ => self.ram[RAM::get_ram_address(addr)],
+        };
+        // 0x4015 is inside the CPU, its value does not reach the external bus
+        if addr != APU_STATUS || cfg!(not(feature = "apu")) {
+            self.open_bus.set(value);
+        }
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoint(addr, value, Access::Read);
+        }
+        value
     }
 
     pub fn write(&mut self, addr: u16, data: u8) {
-        self.ram[RAM::get_ram_address(addr)] = data;
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoint(addr, data, Access::Write);
+        }
+        self.open_bus.set(data);
+        match addr {
+            PPU_REG_START..=PPU_REG_END => self.ppu.write_register(
+                &mut self.cartridge,
+                RAM::get_ram_address(addr) as u16,
+                data,
+            ),
+            #[cfg(feature = "apu")]
+            APU_REG_START..=APU_CHANNELS_END | APU_STATUS | APU_FRAME_COUNTER => {
+                self.apu.write_register(addr, data)
+            }
+            OAM_DMA => self.oam_dma(data),
+            JOY1 => {
+                for controller in &mut self.controllers {
+                    controller.write(data);
+                }
+            }
+            CARTRIDGE_START.. => self.cartridge.write(addr, data),
+            _ => self.ram[RAM::get_ram_address(addr)] = data,
+        }
+    }
+}
+
+impl Bus for RAM {
+    fn read(&mut self, addr: u16) -> u8 {
+        RAM::read(self, addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        RAM::write(self, addr, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cpu::{Vectors, CPU},
+        test_rom,
+    };
+
+    // Powers on a CPU running code from 0x8000 on a RAM with an NROM cartridge
+    fn power_on(code: &[u8]) -> (CPU, RAM) {
+        let mut ram: RAM = RAM::from_bytes(&test_rom::nrom(code)).unwrap();
+        let cpu: CPU = CPU::from_bus(&mut ram);
+        (cpu, ram)
+    }
+
+    #[test]
+    fn cpu_writes_are_visible_in_work_ram() {
+        // LDA #$5A, STA $0123
+        let (mut cpu, mut ram) = power_on(&[0xA9, 0x5A, 0x8D, 0x23, 0x01]);
+        cpu.step(&mut ram).unwrap();
+        cpu.step(&mut ram).unwrap();
+        assert_eq!(ram.work_ram().len(), WORK_RAM_SIZE);
+        assert_eq!(ram.work_ram()[0x0123], 0x5A);
+    }
+
+    #[test]
+    fn write_watchpoint_is_hit_by_a_cpu_write_only() {
+        // LDA $0123, LDA #$5A, STA $0123
+        let (mut cpu, mut ram) = power_on(&[0xAD, 0x23, 0x01, 0xA9, 0x5A, 0x8D, 0x23, 0x01]);
+        ram.add_watchpoint(0x0123, WatchKind::Write);
+        cpu.step(&mut ram).unwrap();
+        cpu.step(&mut ram).unwrap();
+        assert_eq!(ram.take_watch_hit(), None);
+        cpu.step(&mut ram).unwrap();
+        assert_eq!(
+            ram.take_watch_hit(),
+            Some(WatchHit {
+                addr: 0x0123,
+                value: 0x5A,
+                access: Access::Write,
+            })
+        );
+        assert_eq!(ram.take_watch_hit(), None);
+    }
+
+    #[test]
+    fn trainer_is_loaded_at_0x7000_and_skipped_before_prg_rom() {
+        let mut rom: Vec<u8> = test_rom::header(1, 0, 0b100).to_vec();
+        rom.extend((0..TRAINER_SIZE).map(|i| i as u8));
+        rom.extend([0xAA; 0x4000]);
+        let ram: RAM = RAM::from_bytes(&rom).unwrap();
+        assert!(ram.cartridge().header().has_trainer);
+        assert_eq!(ram.peek(0x7000), 0x00);
+        assert_eq!(ram.peek(0x71FF), 0xFF);
+        assert_eq!(ram.peek(0x8000), 0xAA);
+    }
+
+    #[test]
+    fn page_crossing_dummy_read_hits_a_watchpoint() {
+        // LDY #$01, LDA ($10),Y with 0x01FF at 0x0010
+        let (mut cpu, mut ram) = power_on(&[0xA0, 0x01, 0xB1, 0x10]);
+        ram.write(0x0010, 0xFF);
+        ram.write(0x0011, 0x01);
+        ram.write(0x0100, 0x12);
+        ram.add_watchpoint(0x0100, WatchKind::Read);
+        cpu.step(&mut ram).unwrap();
+        cpu.step(&mut ram).unwrap();
+        assert_eq!(
+            ram.take_watch_hit(),
+            Some(WatchHit {
+                addr: 0x0100,
+                value: 0x12,
+                access: Access::Read,
+            })
+        );
+    }
+
+    #[test]
+    fn mmc3_prg_bank_switch_changes_the_byte_read_at_0x8000() {
+        // 8 banks of 8KB filled with 0x10 + their index, the code is in the
+        // last one, fixed at 0xE000
+        let mut rom: Vec<u8> = test_rom::header(4, 0, 0x40).to_vec();
+        for bank in 0..8 {
+            rom.extend([0x10 + bank; 0x2000]);
+        }
+        // LDA $8000, then R6 = 3 through the bank select and bank data
+        // registers, and LDA $8000 again
+        let code: [u8; 16] = [
+            0xAD, 0x00, 0x80, 0xA9, 0x06, 0x8D, 0x00, 0x80, 0xA9, 0x03, 0x8D, 0x01, 0x80, 0xAD,
+            0x00, 0x80,
+        ];
+        let last_bank: usize = rom.len() - 0x2000;
+        rom[last_bank..last_bank + code.len()].copy_from_slice(&code);
+        // reset vector
+        rom[last_bank + 0x1FFC..last_bank + 0x1FFE].copy_from_slice(&[0x00, 0xE0]);
+        let mut ram: RAM = RAM::from_bytes(&rom).unwrap();
+        let mut cpu: CPU = CPU::from_bus(&mut ram);
+        assert_eq!(cpu.pc(), 0xE000);
+        cpu.step(&mut ram).unwrap();
+        assert_eq!(cpu.a(), 0x10);
+        for _ in 0..5 {
+            cpu.step(&mut ram).unwrap();
+        }
+        assert_eq!(cpu.a(), 0x13);
+    }
+
+    #[test]
+    fn cpu_vectors_are_read_from_the_top_of_prg_rom() {
+        let mut rom: Vec<u8> = test_rom::nrom(&[]);
+        let len: usize = rom.len();
+        rom[len - 6..].copy_from_slice(&[0x23, 0x81, 0x56, 0x84, 0x89, 0x87]);
+        let mut ram: RAM = RAM::from_bytes(&rom).unwrap();
+        let vectors: Vectors = CPU::vectors(&mut ram);
+        assert_eq!(
+            (vectors.nmi, vectors.reset, vectors.irq),
+            (0x8123, 0x8456, 0x8789)
+        );
+        assert_eq!(CPU::from_bus(&mut ram).pc(), vectors.reset);
     }
 }
@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+
+// Save states taken every few frames, to go back in time
+// Only the most recent snapshot is kept whole, the older ones are stored as
+// the difference with the next one: consecutive snapshots differ in a few
+// hundred bytes at most, so this takes a fraction of the memory
+#[derive(Debug)]
+pub struct RewindBuffer {
+    // frames between two snapshots
+    interval: u64,
+    // maximum number of snapshots kept, the oldest ones are dropped first
+    capacity: usize,
+    frames_since_snapshot: u64,
+    latest: Option<Vec<u8>>,
+    // oldest first, each one turns the next snapshot into the previous one
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl RewindBuffer {
+    pub fn new(interval: u64, capacity: usize) -> RewindBuffer {
+        assert!(interval > 0, "Rewind interval must be at least one frame");
+        assert!(
+            capacity > 0,
+            "Rewind capacity must be at least one snapshot"
+        );
+        RewindBuffer {
+            interval,
+            capacity,
+            frames_since_snapshot: 0,
+            latest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    // Counts a frame, returns true if a snapshot is due
+    pub fn frame_done(&mut self) -> bool {
+        self.frames_since_snapshot += 1;
+        if self.frames_since_snapshot >= self.interval {
+            self.frames_since_snapshot = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.latest.take() {
+            self.deltas.push_back(diff(&state, &previous));
+        }
+        self.latest = Some(state);
+        if self.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    // Removes the most recent snapshot and returns it
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let state: Vec<u8> = self.latest.take()?;
+        if let Some(delta) = self.deltas.pop_back() {
+            self.latest = Some(patch(&state, &delta));
+        }
+        self.frames_since_snapshot = 0;
+        Some(state)
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.frames_since_snapshot = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.latest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    // Memory used by the snapshots, in bytes
+    pub fn size(&self) -> usize {
+        self.latest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+}
+
+// Delta format: the length of the target, then for every changed span the
+// number of unchanged bytes before it, its length and the target bytes XORed
+// with the source ones, all lengths as u32 little endian
+fn diff(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let byte_at = |data: &[u8], i: usize| data.get(i).copied().unwrap_or(0);
+    let mut delta: Vec<u8> = Vec::new();
+    delta.extend_from_slice(&(target.len() as u32).to_le_bytes());
+    let mut i: usize = 0;
+    let mut unchanged: usize = 0;
+    while i < target.len() {
+        if byte_at(source, i) == target[i] {
+            unchanged += 1;
+            i += 1;
+            continue;
+        }
+        let start: usize = i;
+        while i < target.len() && byte_at(source, i) != target[i] {
+            i += 1;
+        }
+        delta.extend_from_slice(&(unchanged as u32).to_le_bytes());
+        delta.extend_from_slice(&((i - start) as u32).to_le_bytes());
+        delta.extend((start..i).map(|j| byte_at(source, j) ^ target[j]));
+        unchanged = 0;
+    }
+    delta
+}
+
+fn patch(source: &[u8], delta: &[u8]) -> Vec<u8> {
+    let read_u32 = |at: usize| u32::from_le_bytes(delta[at..at + 4].try_into().unwrap()) as usize;
+    let len: usize = read_u32(0);
+    let mut target: Vec<u8> = source.to_vec();
+    target.resize(len, 0);
+    let mut pos: usize = 4;
+    let mut i: usize = 0;
+    while pos < delta.len() {
+        i += read_u32(pos);
+        let span: usize = read_u32(pos + 4);
+        pos += 8;
+        for (byte, change) in target[i..i + span].iter_mut().zip(&delta[pos..pos + span]) {
+            *byte ^= change;
+        }
+        i += span;
+        pos += span;
+    }
+    target
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patch_applies_the_diff_between_states_of_any_length() {
+        let source: Vec<u8> = vec![1, 2, 3, 4, 5, 6];
+        for target in [
+            vec![1, 9, 3, 4, 8, 8],
+            vec![1, 2, 3],
+            vec![1, 2, 3, 4, 5, 6, 7, 0, 9],
+            source.clone(),
+        ] {
+            assert_eq!(patch(&source, &diff(&source, &target)), target);
+        }
+    }
+
+    #[test]
+    fn pop_returns_the_snapshots_from_the_most_recent_one() {
+        let mut buffer: RewindBuffer = RewindBuffer::new(1, 3);
+        let states: [Vec<u8>; 4] = [vec![0; 8], vec![1; 8], vec![1, 2, 1, 2], vec![3; 16]];
+        for state in &states {
+            buffer.push(state.clone());
+        }
+        // the first snapshot went over the capacity
+        assert_eq!(buffer.len(), 3);
+        for state in states[1..].iter().rev() {
+            assert_eq!(buffer.pop().as_ref(), Some(state));
+        }
+        assert_eq!(buffer.pop(), None);
+        assert!(buffer.is_empty());
+    }
+}
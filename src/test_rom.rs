@@ -0,0 +1,33 @@
+// Small ROM images for the unit tests
+
+use crate::cartridge::{Cartridge, Header, HEADER_SIZE, NES_MAGIC};
+
+// iNES header with the given numbers of 16KB PRG ROM and 8KB CHR ROM pages
+// and flags 6 (mirroring, battery, trainer, four-screen and mapper low nibble)
+pub fn header(prg_pages: u8, chr_pages: u8, flags6: u8) -> [u8; HEADER_SIZE] {
+    let mut header: [u8; HEADER_SIZE] = [0; HEADER_SIZE];
+    header[..4].copy_from_slice(&NES_MAGIC);
+    header[4] = prg_pages;
+    header[5] = chr_pages;
+    header[6] = flags6;
+    header
+}
+
+// 32KB NROM ROM with CHR RAM running code from 0x8000, where all the vectors
+// point; the rest of PRG ROM is filled with NOPs
+pub fn nrom(code: &[u8]) -> Vec<u8> {
+    let mut prg_rom: Vec<u8> = vec![0xEA; 0x8000];
+    prg_rom[..code.len()].copy_from_slice(code);
+    for vector in prg_rom[0x7FFA..].chunks_exact_mut(2) {
+        vector.copy_from_slice(&[0x00, 0x80]);
+    }
+    let mut rom: Vec<u8> = header(2, 0, 0).to_vec();
+    rom.extend(prg_rom);
+    rom
+}
+
+// NROM cartridge with 8KB of CHR RAM, for the tests of the PPU
+pub fn cartridge(flags6: u8) -> Cartridge {
+    let header: Header = Header::parse(&header(1, 0, flags6)).unwrap();
+    Cartridge::new(header, vec![0; 0x4000], Vec::new(), None)
+}
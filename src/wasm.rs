@@ -0,0 +1,52 @@
+// Browser API, built for wasm32-unknown-unknown with the wasm feature
+//
+// A JS shim loads the ROM bytes, then on every animation frame:
+//   nes.set_buttons(buttons);
+//   nes.step_frame();
+//   ctx.putImageData(new ImageData(new Uint8ClampedArray(nes.frame_buffer()), 256, 240), 0, 0);
+
+use wasm_bindgen::prelude::*;
+
+use crate::nes::{NesBuilder, NES};
+
+#[wasm_bindgen]
+pub struct WasmNes {
+    nes: NES,
+}
+
+#[wasm_bindgen]
+impl WasmNes {
+    // Parses an iNES ROM; PRG RAM is not persisted
+    #[wasm_bindgen(constructor)]
+    pub fn load_rom(data: &[u8]) -> Result<WasmNes, JsError> {
+        Ok(WasmNes {
+            nes: NesBuilder::from_bytes(data).trace(false).build()?,
+        })
+    }
+
+    // Runs the emulation for one frame, without any throttling
+    pub fn step_frame(&mut self) -> Result<(), JsError> {
+        Ok(self.nes.run_frames(1)?)
+    }
+
+    // 256x240 pixels, row by row from the top left, 4 bytes per pixel in R, G, B, A
+    // order with A always 0xFF: the layout canvas ImageData expects
+    pub fn frame_buffer(&self) -> Vec<u8> {
+        self.nes.ppu().frame_buffer().to_vec()
+    }
+
+    // Controller 1, one bit per button: A, B, Select, Start, Up, Down, Left, Right
+    // from bit 0 to bit 7
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.nes.controller_mut(0).set_buttons(buttons);
+    }
+
+    // Same as set_buttons, for controller 2
+    pub fn set_buttons_2(&mut self, buttons: u8) {
+        self.nes.controller_mut(1).set_buttons(buttons);
+    }
+
+    pub fn reset(&mut self) {
+        self.nes.reset();
+    }
+}